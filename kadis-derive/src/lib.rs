@@ -0,0 +1,109 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+
+//! `#[derive(KadisHash)]`, which stores each field of a struct as a separate
+//! field of a Kadis hash.
+//!
+//! Fields can be renamed with `#[kadis(rename = "name")]` or left out with
+//! `#[kadis(skip)]`, in which case they are filled with `Default::default()`
+//! when loaded.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Ident, LitStr};
+
+struct Field {
+	ident: Ident,
+	name: String,
+	skip: bool,
+}
+
+fn parse_field(field: &syn::Field) -> Result<Field, Error> {
+	let ident = match &field.ident {
+		Some(ident) => ident.clone(),
+		None => return Err(Error::new_spanned(field, "KadisHash fields must be named")),
+	};
+	let mut name = ident.to_string();
+	let mut skip = false;
+
+	for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("kadis")) {
+		attr.parse_nested_meta(|meta| {
+			if meta.path.is_ident("skip") {
+				skip = true;
+				Ok(())
+			} else if meta.path.is_ident("rename") {
+				name = meta.value()?.parse::<LitStr>()?.value();
+				Ok(())
+			} else {
+				Err(meta.error("expected `rename` or `skip`"))
+			}
+		})?;
+	}
+
+	Ok(Field { ident, name, skip })
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+	let fields = match &input.data {
+		Data::Struct(data) => match &data.fields {
+			Fields::Named(fields) => &fields.named,
+			_ => return Err(Error::new_spanned(&input, "KadisHash needs a struct with named fields")),
+		},
+		_ => return Err(Error::new_spanned(&input, "KadisHash can only be derived for structs")),
+	};
+	let fields = fields.iter().map(parse_field).collect::<Result<Vec<_>, _>>()?;
+
+	let stored: Vec<_> = fields.iter().filter(|field| !field.skip).collect();
+	let names: Vec<_> = stored.iter().map(|field| &field.name).collect();
+	let idents: Vec<_> = stored.iter().map(|field| &field.ident).collect();
+	let skipped: Vec<_> = fields.iter().filter(|field| field.skip).map(|field| &field.ident).collect();
+
+	let ty = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+	Ok(quote! {
+		impl #impl_generics ::kadis::HashFields for #ty #ty_generics #where_clause {
+			fn fields() -> &'static [&'static str] {
+				&[#(#names),*]
+			}
+
+			fn encode_fields(&self, codec: &(dyn ::kadis::Codec + 'static)) -> ::std::result::Result<::std::vec::Vec<::std::vec::Vec<u8>>, (&'static str, ::kadis::CodecError)> {
+				::std::result::Result::Ok(::std::vec![
+					#(codec.encode(&self.#idents).map_err(|err| (#names, err))?),*
+				])
+			}
+
+			fn decode_fields(values: &[::std::vec::Vec<u8>], codec: &(dyn ::kadis::Codec + 'static)) -> ::std::result::Result<Self, (&'static str, ::kadis::CodecError)> {
+				let mut values = values.iter();
+				::std::result::Result::Ok(Self {
+					#(#idents: codec.decode(values.next().map(|value| value.as_slice()).unwrap_or_default()).map_err(|err| (#names, err))?,)*
+					#(#skipped: ::std::default::Default::default(),)*
+				})
+			}
+		}
+	})
+}
+
+#[proc_macro_derive(KadisHash, attributes(kadis))]
+pub fn derive_kadis_hash(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	match expand(input) {
+		Ok(tokens) => tokens.into(),
+		Err(err) => err.to_compile_error().into(),
+	}
+}
@@ -0,0 +1,52 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+
+use kadis::{Bincode, Codec, HashFields, Json};
+use kadis_derive::KadisHash;
+
+#[derive(Debug, PartialEq, KadisHash)]
+struct User {
+	name: String,
+	#[kadis(rename = "years")]
+	age: u32,
+	#[kadis(skip)]
+	session: Option<String>,
+}
+
+#[test]
+fn round_trip() {
+	assert_eq!(User::fields(), &["name", "years"]);
+
+	let user = User {
+		name: "Herbert".into(),
+		age: 7,
+		session: Some("abc".into()),
+	};
+	let codec: &dyn Codec = &Bincode;
+	let values = user.encode_fields(codec).unwrap();
+	assert_eq!(values.len(), 2);
+
+	let decoded = User::decode_fields(&values, codec).unwrap();
+	assert_eq!(decoded, User {
+		session: None,
+		..user
+	});
+
+	let (field, _) = User::decode_fields(&values, &Json).unwrap_err();
+	assert_eq!(field, "name");
+	assert!(User::decode_fields(&values[..1], codec).is_err());
+}
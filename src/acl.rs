@@ -0,0 +1,268 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use fnv::FnvHashMap;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use crate::impl_error;
+use crate::signature;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Permission {
+	Read,
+	Write,
+	Delete,
+}
+
+/// Who may read, write and delete keys starting with a prefix. A permission
+/// left unset is granted to everyone. Only admins may replace the ACL itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Acl {
+	admins: Vec<PeerId>,
+	read: Option<Vec<PeerId>>,
+	write: Option<Vec<PeerId>>,
+	delete: Option<Vec<PeerId>>,
+}
+
+// PeerIds have no serde support, so they are stored as bytes
+#[derive(Deserialize, Serialize)]
+struct AclRecord {
+	admins: Vec<Vec<u8>>,
+	read: Option<Vec<Vec<u8>>>,
+	write: Option<Vec<Vec<u8>>>,
+	delete: Option<Vec<Vec<u8>>>,
+}
+
+fn to_bytes(peers: &[PeerId]) -> Vec<Vec<u8>> {
+	peers.iter().map(|peer| peer.as_bytes().to_vec()).collect()
+}
+
+fn from_bytes(peers: Vec<Vec<u8>>) -> Result<Vec<PeerId>, String> {
+	peers.into_iter()
+		.map(|peer| PeerId::from_bytes(peer).map_err(|_| "invalid peer id".to_string()))
+		.collect()
+}
+
+impl Acl {
+	pub fn new(admins: &[PeerId]) -> Self {
+		Self {
+			admins: admins.to_vec(),
+			read: None,
+			write: None,
+			delete: None,
+		}
+	}
+
+	pub fn read(&self, peers: &[PeerId]) -> Self {
+		Self {
+			read: Some(peers.to_vec()),
+			..self.clone()
+		}
+	}
+
+	pub fn write(&self, peers: &[PeerId]) -> Self {
+		Self {
+			write: Some(peers.to_vec()),
+			..self.clone()
+		}
+	}
+
+	pub fn delete(&self, peers: &[PeerId]) -> Self {
+		Self {
+			delete: Some(peers.to_vec()),
+			..self.clone()
+		}
+	}
+
+	pub fn admins(&self) -> &[PeerId] {
+		&self.admins
+	}
+
+	pub fn permits(&self, peer: Option<&PeerId>, permission: Permission) -> bool {
+		let peers = match permission {
+			Permission::Read => &self.read,
+			Permission::Write => &self.write,
+			Permission::Delete => &self.delete,
+		};
+		match (peers, peer) {
+			(None, _) => true,
+			(Some(peers), Some(peer)) => peers.contains(peer),
+			(Some(_), None) => false,
+		}
+	}
+
+	pub fn encode(&self) -> Vec<u8> {
+		let record = AclRecord {
+			admins: to_bytes(&self.admins),
+			read: self.read.as_deref().map(to_bytes),
+			write: self.write.as_deref().map(to_bytes),
+			delete: self.delete.as_deref().map(to_bytes),
+		};
+		bincode::serialize(&record).unwrap_or_default()
+	}
+
+	pub fn decode(data: &[u8]) -> Result<Self, String> {
+		let record: AclRecord = bincode::deserialize(data).map_err(|err| err.to_string())?;
+		Ok(Self {
+			admins: from_bytes(record.admins)?,
+			read: record.read.map(from_bytes).transpose()?,
+			write: record.write.map(from_bytes).transpose()?,
+			delete: record.delete.map(from_bytes).transpose()?,
+		})
+	}
+}
+
+pub fn acl_key(prefix: &str) -> String {
+	format!("ka-{}", prefix)
+}
+
+pub fn acl_prefix(key: &str) -> Option<&str> {
+	key.strip_prefix("ka-")
+}
+
+/// ACLs this node has seen, either stored on it, announced by their admins
+/// or fetched by a client. Used by storage and client-side checks alike.
+pub struct AclTable {
+	acls: FnvHashMap<String, Acl>,
+	// Peers every node trusts to set ACLs that no other ACL governs
+	root: Vec<PeerId>,
+}
+
+impl AclTable {
+	pub fn new(root: &[PeerId]) -> Self {
+		Self {
+			acls: FnvHashMap::default(),
+			root: root.to_vec(),
+		}
+	}
+
+	/// Finds the ACL for `key`, preferring the longest matching prefix.
+	pub fn find(&self, key: &str) -> Option<&Acl> {
+		self.acls.iter()
+			.filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+			.max_by_key(|(prefix, _)| prefix.len())
+			.map(|(_, acl)| acl)
+	}
+
+	pub fn permits(&self, key: &str, peer: Option<&PeerId>, permission: Permission) -> bool {
+		match self.find(key) {
+			Some(acl) => acl.permits(peer, permission),
+			None => true,
+		}
+	}
+
+	/// Whether `signer` may set the ACL for `prefix`, which takes an admin of
+	/// the ACL currently governing it. ACLs with none above them can only be
+	/// set by the root admins, which every node is configured with.
+	pub fn may_update(&self, prefix: &str, signer: Option<&PeerId>) -> bool {
+		match (self.find(prefix), signer) {
+			(Some(acl), Some(signer)) => acl.admins.contains(signer),
+			(None, Some(signer)) => self.root.contains(signer),
+			(_, None) => false,
+		}
+	}
+
+	/// Checks a signed ACL record stored under `key`, returning its prefix and ACL.
+	pub fn validate<'a>(&self, key: &'a str, record: &[u8]) -> Result<(&'a str, Acl), String> {
+		let prefix = acl_prefix(key).ok_or("not an ACL key")?;
+		let signed = signature::open(key, record, true)?;
+		if signed.signer.is_none() {
+			return Err("ACL is not signed".into());
+		}
+		if !self.may_update(prefix, signed.signer.as_ref()) {
+			return Err(format!("signer is not an admin of {}", prefix));
+		}
+		Ok((prefix, Acl::decode(signed.payload)?))
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.acls.is_empty()
+	}
+
+	pub fn insert(&mut self, prefix: &str, acl: Acl) {
+		self.acls.insert(prefix.into(), acl);
+	}
+}
+
+#[derive(Debug)]
+pub enum AclError {
+	NotFound {
+		prefix: String,
+	},
+	QuorumFailed {
+		prefix: String,
+	},
+	Timeout {
+		prefix: String,
+	},
+	Rejected {
+		prefix: String,
+	},
+	Invalid {
+		prefix: String,
+	},
+	PermissionDenied {
+		prefix: String,
+	},
+}
+
+impl_error!(AclError => Acl {
+	NotFound { prefix } => NotFound, "no ACL for prefix `{prefix}`",
+	QuorumFailed { prefix } => QuorumFailed, "quorum failed for the ACL of prefix `{prefix}`",
+	Timeout { prefix } => Timeout, "request timed out for the ACL of prefix `{prefix}`",
+	Rejected { prefix } => Rejected, "ACL for prefix `{prefix}` rejected by local store",
+	Invalid { prefix } => Invalid, "invalid ACL record for prefix `{prefix}`",
+	PermissionDenied { prefix } => PermissionDenied, "permission denied for the ACL of prefix `{prefix}`",
+});
+
+#[cfg(test)]
+mod tests {
+	use libp2p::PeerId;
+
+	use super::{Acl, AclTable, Permission};
+
+	#[test]
+	fn table() {
+		let alice = PeerId::random();
+		let bob = PeerId::random();
+
+		let admins = vec![alice.clone()];
+
+		let acl = Acl::new(&admins)
+			.write(&admins)
+			.delete(&[]);
+		assert_eq!(Acl::decode(&acl.encode()).unwrap(), acl);
+
+		let mut table = AclTable::new(&admins);
+		assert!(!table.may_update("team-a:", Some(&bob)));
+		assert!(table.may_update("team-a:", Some(&alice)));
+		table.insert("team-a:", acl);
+		assert!(!table.may_update("team-a:", Some(&bob)));
+		assert!(table.may_update("team-a:", Some(&alice)));
+		// Longer prefixes are governed by the ACL above them
+		assert!(!table.may_update("team-a:public:", Some(&bob)));
+		assert!(table.may_update("team-a:public:", Some(&alice)));
+		table.insert("team-a:public:", Acl::new(&admins));
+
+		assert!(table.permits("team-a:cats", Some(&alice), Permission::Write));
+		assert!(!table.permits("team-a:cats", Some(&bob), Permission::Write));
+		assert!(table.permits("team-a:cats", Some(&bob), Permission::Read));
+		assert!(!table.permits("team-a:cats", Some(&alice), Permission::Delete));
+		assert!(table.permits("team-a:public:cats", Some(&bob), Permission::Write));
+		assert!(table.permits("team-b:cats", None, Permission::Delete));
+	}
+}
@@ -0,0 +1,197 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::{
+	collections::BTreeMap,
+	time::{Duration, Instant},
+};
+
+use fnv::FnvHashMap;
+
+#[derive(Clone, Copy, Debug)]
+pub struct CacheConfig {
+	pub capacity: usize,
+	pub lifetime: Duration,
+	pub read_through: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+	pub hits: u64,
+	pub misses: u64,
+	pub evictions: u64,
+	pub invalidations: u64,
+}
+
+struct Entry {
+	value: Vec<u8>,
+	expires: Instant,
+	tick: u64,
+}
+
+/// Size-bounded LRU cache where every entry also expires after `lifetime`.
+pub struct Cache {
+	config: CacheConfig,
+	entries: FnvHashMap<String, Entry>,
+	// Maps the last-used tick of each entry to its key, oldest first
+	recency: BTreeMap<u64, String>,
+	tick: u64,
+	stats: CacheStats,
+}
+
+impl Cache {
+	pub fn new(config: CacheConfig) -> Self {
+		Self {
+			config,
+			entries: FnvHashMap::default(),
+			recency: BTreeMap::new(),
+			tick: 0,
+			stats: CacheStats::default(),
+		}
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		self.stats
+	}
+
+	fn next_tick(&mut self) -> u64 {
+		self.tick += 1;
+		self.tick
+	}
+
+	pub fn get(&mut self, key: &str) -> Option<Vec<u8>> {
+		let tick = self.next_tick();
+
+		let expired = match self.entries.get_mut(key) {
+			Some(entry) => {
+				if entry.expires > Instant::now() {
+					self.recency.remove(&entry.tick);
+					self.recency.insert(tick, key.into());
+					entry.tick = tick;
+					self.stats.hits += 1;
+					return Some(entry.value.clone());
+				}
+				true
+			},
+			None => false,
+		};
+
+		if expired {
+			self.remove(key);
+		}
+
+		self.stats.misses += 1;
+		None
+	}
+
+	pub fn insert(&mut self, key: &str, value: Vec<u8>) {
+		if self.config.capacity == 0 {
+			return;
+		}
+
+		self.remove(key);
+
+		while self.entries.len() >= self.config.capacity {
+			let oldest = match self.recency.keys().next() {
+				Some(tick) => *tick,
+				None => break,
+			};
+			if let Some(key) = self.recency.remove(&oldest) {
+				self.entries.remove(&key);
+				self.stats.evictions += 1;
+			}
+		}
+
+		let tick = self.next_tick();
+		let entry = Entry {
+			value,
+			expires: Instant::now() + self.config.lifetime,
+			tick,
+		};
+		self.recency.insert(tick, key.into());
+		self.entries.insert(key.into(), entry);
+	}
+
+	#[cfg(any(test, feature = "testing"))]
+	pub fn clear(&mut self) {
+		self.entries.clear();
+		self.recency.clear();
+	}
+
+	/// Drops `key` because it was changed elsewhere.
+	pub fn invalidate(&mut self, key: &str) {
+		if self.remove(key) {
+			self.stats.invalidations += 1;
+		}
+	}
+
+	fn remove(&mut self, key: &str) -> bool {
+		match self.entries.remove(key) {
+			Some(entry) => {
+				self.recency.remove(&entry.tick);
+				true
+			},
+			None => false,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{thread, time::Duration};
+
+	use super::{Cache, CacheConfig};
+
+	fn cache(capacity: usize, lifetime: Duration) -> Cache {
+		Cache::new(CacheConfig {
+			capacity,
+			lifetime,
+			read_through: true,
+		})
+	}
+
+	#[test]
+	fn evicts_least_recently_used() {
+		let mut cache = cache(2, Duration::from_secs(60));
+		cache.insert("a", vec![1]);
+		cache.insert("b", vec![2]);
+		assert_eq!(cache.get("a"), Some(vec![1]));
+
+		cache.insert("c", vec![3]);
+		assert_eq!(cache.get("b"), None);
+		assert_eq!(cache.get("a"), Some(vec![1]));
+		assert_eq!(cache.get("c"), Some(vec![3]));
+
+		let stats = cache.stats();
+		assert_eq!(stats.hits, 3);
+		assert_eq!(stats.misses, 1);
+		assert_eq!(stats.evictions, 1);
+	}
+
+	#[test]
+	fn expires_and_invalidates() {
+		let mut cache = cache(8, Duration::from_millis(20));
+		cache.insert("a", vec![1]);
+		cache.insert("b", vec![2]);
+
+		cache.invalidate("b");
+		assert_eq!(cache.get("b"), None);
+
+		thread::sleep(Duration::from_millis(30));
+		assert_eq!(cache.get("a"), None);
+		assert_eq!(cache.stats().invalidations, 1);
+	}
+}
@@ -0,0 +1,195 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::convert::TryInto;
+
+use sha2::{Digest, Sha256};
+
+use crate::compression::MAX_VALUE_SIZE;
+
+// Kademlia refuses packets above 16 KiB, so records are kept well below that
+pub const CHUNK_SIZE: usize = 8 * 1024;
+
+// Manifests share the first byte with the compression flags, which never use it
+const MANIFEST: u8 = 0x4d;
+const VERSION: u8 = 1;
+const DIGEST_LEN: usize = 32;
+const HEADER_LEN: usize = 2 + 8 + DIGEST_LEN;
+// Values grow a little when compressed or sealed, so records may exceed the largest value
+const MAX_RECORD_SIZE: u64 = (MAX_VALUE_SIZE + CHUNK_SIZE) as u64;
+
+type Digest256 = [u8; DIGEST_LEN];
+
+pub struct Manifest {
+	len: u64,
+	digest: Digest256,
+	chunks: Vec<Digest256>,
+}
+
+fn digest(data: &[u8]) -> Digest256 {
+	Sha256::digest(data).into()
+}
+
+fn chunk_key(digest: &Digest256) -> String {
+	let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+	format!("kc-{}", hex)
+}
+
+pub fn is_manifest(record: &[u8]) -> bool {
+	record.first() == Some(&MANIFEST)
+}
+
+pub fn is_chunk_key(key: &str) -> bool {
+	key.starts_with("kc-")
+}
+
+/// Whether `chunk` is the content its chunk key was derived from.
+pub fn matches_key(key: &str, chunk: &[u8]) -> bool {
+	chunk_key(&digest(chunk)) == key
+}
+
+/// Splits the record stored under `key` into content-addressed chunks and
+/// returns the manifest record that replaces it, along with the chunk records
+/// to store. Chunks start with the digest of `key`, so records never share
+/// them and each can remove its own once overwritten.
+pub fn split(key: &str, record: &[u8]) -> (Vec<u8>, Vec<(String, Vec<u8>)>) {
+	let salt = digest(key.as_bytes());
+	let mut manifest = Vec::with_capacity(HEADER_LEN + record.len() / CHUNK_SIZE * DIGEST_LEN + DIGEST_LEN);
+	manifest.push(MANIFEST);
+	manifest.push(VERSION);
+	manifest.extend_from_slice(&(record.len() as u64).to_le_bytes());
+	manifest.extend_from_slice(&digest(record));
+
+	let chunks = record.chunks(CHUNK_SIZE)
+		.map(|chunk| {
+			let mut salted = Vec::with_capacity(DIGEST_LEN + chunk.len());
+			salted.extend_from_slice(&salt);
+			salted.extend_from_slice(chunk);
+			let digest = digest(&salted);
+			manifest.extend_from_slice(&digest);
+			(chunk_key(&digest), salted)
+		})
+		.collect();
+
+	(manifest, chunks)
+}
+
+impl Manifest {
+	pub fn parse(record: &[u8]) -> Result<Self, String> {
+		if record.len() < HEADER_LEN || !is_manifest(record) {
+			return Err("manifest is truncated".into());
+		}
+		if record[1] != VERSION {
+			return Err(format!("unknown manifest version {}", record[1]));
+		}
+
+		let len = u64::from_le_bytes(record[2..10].try_into().unwrap_or_default());
+		let digest = record[10..HEADER_LEN].try_into().unwrap_or_default();
+
+		let digests = &record[HEADER_LEN..];
+		if digests.len() % DIGEST_LEN != 0 {
+			return Err("manifest has a partial chunk digest".into());
+		}
+		let chunks: Vec<Digest256> = digests.chunks(DIGEST_LEN)
+			.map(|d| d.try_into().unwrap_or_default())
+			.collect();
+
+		// The length is only trusted once it needs exactly the chunks listed
+		let count = chunks.len() as u64;
+		let chunk_size = CHUNK_SIZE as u64;
+		if len > MAX_RECORD_SIZE || len > count * chunk_size || len <= count.saturating_sub(1) * chunk_size {
+			return Err(format!("manifest length {} does not fit its {} chunks", len, count));
+		}
+
+		Ok(Self {
+			len,
+			digest,
+			chunks,
+		})
+	}
+
+	pub fn keys(&self) -> Vec<String> {
+		self.chunks.iter().map(chunk_key).collect()
+	}
+
+	/// Joins the chunks fetched for the record stored under `key` back
+	/// together, checking every digest.
+	pub fn assemble(&self, key: &str, chunks: Vec<Vec<u8>>) -> Result<Vec<u8>, String> {
+		let salt = digest(key.as_bytes());
+		if chunks.len() != self.chunks.len() {
+			return Err(format!("expected {} chunks but got {}", self.chunks.len(), chunks.len()));
+		}
+
+		let mut record = Vec::with_capacity(self.len as usize);
+		for (index, (chunk, expected)) in chunks.iter().zip(&self.chunks).enumerate() {
+			if digest(chunk) != *expected {
+				return Err(format!("chunk {} does not match its digest", index));
+			}
+			if !chunk.starts_with(&salt) {
+				return Err(format!("chunk {} belongs to another record", index));
+			}
+			record.extend_from_slice(&chunk[DIGEST_LEN..]);
+		}
+
+		if record.len() as u64 != self.len || digest(&record) != self.digest {
+			return Err("reassembled record does not match its digest".into());
+		}
+
+		Ok(record)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{is_chunk_key, is_manifest, matches_key, split, Manifest, CHUNK_SIZE};
+
+	#[test]
+	fn split_and_assemble() {
+		let record: Vec<u8> = (0..CHUNK_SIZE * 3 + 17).map(|i| (i % 251) as u8).collect();
+		let (manifest, chunks) = split("kh-cats-herb", &record);
+		assert!(is_manifest(&manifest));
+		assert_eq!(chunks.len(), 4);
+
+		let parsed = Manifest::parse(&manifest).unwrap();
+		let keys = parsed.keys();
+		assert_eq!(keys, chunks.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>());
+		assert!(chunks.iter().all(|(key, chunk)| is_chunk_key(key) && matches_key(key, chunk)));
+
+		let mut data: Vec<Vec<u8>> = chunks.into_iter().map(|(_, chunk)| chunk).collect();
+		assert_eq!(parsed.assemble("kh-cats-herb", data.clone()).unwrap(), record);
+		assert!(parsed.assemble("kh-cats-ferb", data.clone()).is_err());
+
+		// The same content stored under another key gets its own chunks
+		let (_, others) = split("kh-cats-ferb", &record);
+		assert!(others.iter().all(|(key, _)| !keys.contains(key)));
+
+		data[2][0] ^= 1;
+		assert!(parsed.assemble("kh-cats-herb", data).is_err());
+	}
+
+	#[test]
+	fn crafted_length() {
+		let record: Vec<u8> = (0..CHUNK_SIZE + 1).map(|i| (i % 251) as u8).collect();
+		let (manifest, _) = split("kh-cats-herb", &record);
+		assert!(Manifest::parse(&manifest).is_ok());
+
+		for len in &[u64::MAX, CHUNK_SIZE as u64 * 2 + 1, CHUNK_SIZE as u64, 0] {
+			let mut crafted = manifest.clone();
+			crafted[2..10].copy_from_slice(&len.to_le_bytes());
+			assert!(Manifest::parse(&crafted).is_err(), "length {}", len);
+		}
+	}
+}
@@ -0,0 +1,278 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::{error::Error, fmt};
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+pub const RAW_TAG: u8 = 0;
+pub const BINCODE_TAG: u8 = 1;
+pub const JSON_TAG: u8 = 2;
+pub const CBOR_TAG: u8 = 3;
+pub const MESSAGE_PACK_TAG: u8 = 4;
+
+#[derive(Debug)]
+pub enum CodecError {
+	Serialize {
+		reason: String,
+	},
+	Deserialize {
+		reason: String,
+	},
+	Mismatch {
+		expected: u8,
+		found: u8,
+	},
+	Untagged,
+}
+
+impl fmt::Display for CodecError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			CodecError::Serialize { reason } => write!(f, "could not serialize value: {}", reason),
+			CodecError::Deserialize { reason } => write!(f, "could not deserialize value: {}", reason),
+			CodecError::Mismatch { expected, found } => {
+				write!(f, "value was stored with codec {} but codec {} was used to read it", found, expected)
+			},
+			CodecError::Untagged => write!(f, "value has no codec tag"),
+		}
+	}
+}
+
+impl Error for CodecError {}
+
+fn serialize_error(err: impl fmt::Display) -> CodecError {
+	CodecError::Serialize { reason: err.to_string() }
+}
+
+fn deserialize_error(err: impl fmt::Display) -> CodecError {
+	CodecError::Deserialize { reason: err.to_string() }
+}
+
+pub type Visit<'a> = dyn FnMut(&mut dyn erased_serde::Deserializer<'_>) -> Result<(), erased_serde::Error> + 'a;
+
+/// Turns values into bytes and back. Every stored value is prefixed with the
+/// codec's tag so that reading it back with another codec fails cleanly.
+pub trait Codec: Send + Sync {
+	fn tag(&self) -> u8;
+
+	fn serialize(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError>;
+
+	/// Hands a deserializer over `data` to `visit`.
+	fn deserialize(&self, data: &[u8], visit: &mut Visit<'_>) -> Result<(), CodecError>;
+}
+
+impl dyn Codec {
+	pub fn encode<T>(&self, value: &T) -> Result<Vec<u8>, CodecError>
+	where T: Serialize {
+		let mut data = vec![self.tag()];
+		data.extend(self.serialize(value)?);
+		Ok(data)
+	}
+
+	pub fn decode<T>(&self, data: &[u8]) -> Result<T, CodecError>
+	where T: DeserializeOwned {
+		match data.first() {
+			Some(tag) if *tag == self.tag() => (),
+			Some(tag) => return Err(CodecError::Mismatch {
+				expected: self.tag(),
+				found: *tag,
+			}),
+			None => return Err(CodecError::Untagged),
+		}
+
+		let mut value = None;
+		self.deserialize(&data[1..], &mut |de| {
+			value = Some(erased_serde::deserialize::<T>(de)?);
+			Ok(())
+		})?;
+
+		value.ok_or(CodecError::Deserialize { reason: "no value produced".into() })
+	}
+}
+
+/// Tags `data` as raw bytes.
+pub fn raw(data: &[u8]) -> Vec<u8> {
+	let mut tagged = Vec::with_capacity(data.len() + 1);
+	tagged.push(RAW_TAG);
+	tagged.extend_from_slice(data);
+	tagged
+}
+
+/// Tags a value written before values carried a codec tag, when all of them
+/// were bincode.
+pub fn legacy(data: &[u8]) -> Vec<u8> {
+	let mut tagged = Vec::with_capacity(data.len() + 1);
+	tagged.push(BINCODE_TAG);
+	tagged.extend_from_slice(data);
+	tagged
+}
+
+/// Strips the codec tag from a stored value, whichever codec wrote it.
+pub fn payload(mut data: Vec<u8>) -> Result<Vec<u8>, CodecError> {
+	if data.is_empty() {
+		return Err(CodecError::Untagged);
+	}
+	data.remove(0);
+	Ok(data)
+}
+
+/// Looks up one of the built-in codecs by tag.
+pub fn builtin(tag: u8) -> Option<&'static dyn Codec> {
+	match tag {
+		BINCODE_TAG => Some(&Bincode),
+		JSON_TAG => Some(&Json),
+		CBOR_TAG => Some(&Cbor),
+		MESSAGE_PACK_TAG => Some(&MessagePack),
+		_ => None,
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+	fn tag(&self) -> u8 {
+		BINCODE_TAG
+	}
+
+	fn serialize(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+		bincode::serialize(value).map_err(serialize_error)
+	}
+
+	fn deserialize(&self, data: &[u8], visit: &mut Visit<'_>) -> Result<(), CodecError> {
+		// Same options as `bincode::deserialize`
+		let options = bincode::DefaultOptions::new()
+			.with_fixint_encoding()
+			.allow_trailing_bytes();
+		let mut de = bincode::Deserializer::from_slice(data, options);
+		visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de)).map_err(deserialize_error)
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl Codec for Json {
+	fn tag(&self) -> u8 {
+		JSON_TAG
+	}
+
+	fn serialize(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+		serde_json::to_vec(value).map_err(serialize_error)
+	}
+
+	fn deserialize(&self, data: &[u8], visit: &mut Visit<'_>) -> Result<(), CodecError> {
+		let mut de = serde_json::Deserializer::from_slice(data);
+		visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de)).map_err(deserialize_error)?;
+		de.end().map_err(deserialize_error)
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cbor;
+
+impl Codec for Cbor {
+	fn tag(&self) -> u8 {
+		CBOR_TAG
+	}
+
+	fn serialize(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+		serde_cbor::to_vec(&value).map_err(serialize_error)
+	}
+
+	fn deserialize(&self, data: &[u8], visit: &mut Visit<'_>) -> Result<(), CodecError> {
+		let mut de = serde_cbor::Deserializer::from_slice(data);
+		visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de)).map_err(deserialize_error)?;
+		de.end().map_err(deserialize_error)
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePack;
+
+impl Codec for MessagePack {
+	fn tag(&self) -> u8 {
+		MESSAGE_PACK_TAG
+	}
+
+	fn serialize(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+		// Structs are written as maps so other languages can read them
+		rmp_serde::to_vec_named(value).map_err(serialize_error)
+	}
+
+	fn deserialize(&self, data: &[u8], visit: &mut Visit<'_>) -> Result<(), CodecError> {
+		let mut de = rmp_serde::Deserializer::from_read_ref(data);
+		visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de)).map_err(deserialize_error)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use serde::{Deserialize, Serialize};
+
+	use super::{builtin, payload, raw, Codec, CodecError, BINCODE_TAG, JSON_TAG, CBOR_TAG, MESSAGE_PACK_TAG};
+
+	#[derive(Debug, PartialEq, Deserialize, Serialize)]
+	struct Cat {
+		name: String,
+		color: String,
+		lives: u8,
+		toys: HashMap<String, f32>,
+	}
+
+	fn cat() -> Cat {
+		let mut toys = HashMap::new();
+		toys.insert("mouse".into(), 0.5);
+		Cat {
+			name: "Herbert".into(),
+			color: "orange".into(),
+			lives: 9,
+			toys,
+		}
+	}
+
+	#[test]
+	fn round_trip() {
+		for tag in &[BINCODE_TAG, JSON_TAG, CBOR_TAG, MESSAGE_PACK_TAG] {
+			let codec: &dyn Codec = builtin(*tag).unwrap();
+			let data = codec.encode(&cat()).unwrap();
+			assert_eq!(data[0], *tag);
+			assert_eq!(codec.decode::<Cat>(&data).unwrap(), cat());
+		}
+	}
+
+	#[test]
+	fn mismatch() {
+		let json: &dyn Codec = builtin(JSON_TAG).unwrap();
+		let bincode: &dyn Codec = builtin(BINCODE_TAG).unwrap();
+		let data = json.encode(&cat()).unwrap();
+
+		match bincode.decode::<Cat>(&data) {
+			Err(CodecError::Mismatch { expected, found }) => {
+				assert_eq!(expected, BINCODE_TAG);
+				assert_eq!(found, JSON_TAG);
+			},
+			_ => panic!("expected codec mismatch"),
+		}
+
+		assert_eq!(payload(data).unwrap(), br#"{"name":"Herbert","color":"orange","lives":9,"toys":{"mouse":0.5}}"#.to_vec());
+		assert_eq!(payload(raw(b"abc")).unwrap(), b"abc".to_vec());
+	}
+}
@@ -0,0 +1,135 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::convert::TryInto;
+use std::io::Read;
+
+// Every record value starts with one of these flags
+const PLAIN: u8 = 0;
+const LZ4: u8 = 1;
+const ZSTD: u8 = 2;
+
+/// Largest value a record may decompress to. Sizes come from other peers, so
+/// they are checked before anything is allocated for them.
+pub const MAX_VALUE_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+	None,
+	Lz4,
+	Zstd,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CompressionConfig {
+	pub compression: Compression,
+	pub threshold: usize,
+}
+
+/// Adds the record header to `value`, compressing it when it is at least
+/// `threshold` bytes long and compression actually makes it smaller.
+pub fn compress(config: CompressionConfig, value: &[u8]) -> Vec<u8> {
+	let compressed = if value.len() < config.threshold {
+		None
+	} else {
+		match config.compression {
+			Compression::None => None,
+			Compression::Lz4 => Some((LZ4, lz4_flex::compress_prepend_size(value))),
+			Compression::Zstd => zstd::encode_all(value, zstd::DEFAULT_COMPRESSION_LEVEL)
+				.ok()
+				.map(|data| (ZSTD, data)),
+		}
+	};
+
+	let (flag, data) = match compressed {
+		Some((flag, data)) if data.len() < value.len() => (flag, data),
+		_ => (PLAIN, value.to_vec()),
+	};
+
+	let mut record = Vec::with_capacity(data.len() + 1);
+	record.push(flag);
+	record.extend(data);
+	record
+}
+
+/// Strips the record header, decompressing regardless of local settings.
+pub fn decompress(record: &[u8]) -> Result<Vec<u8>, String> {
+	let (flag, data) = match record.split_first() {
+		Some((flag, data)) => (*flag, data),
+		None => return Err("record has no header".into()),
+	};
+
+	match flag {
+		PLAIN => Ok(data.to_vec()),
+		LZ4 => {
+			if data.len() < 4 {
+				return Err("lz4 record has no size".into());
+			}
+			let (size, data) = data.split_at(4);
+			let size = u32::from_le_bytes(size.try_into().unwrap_or_default()) as usize;
+			if size > MAX_VALUE_SIZE {
+				return Err(format!("value of {} bytes is too large", size));
+			}
+			lz4_flex::decompress(data, size).map_err(|err| err.to_string())
+		},
+		ZSTD => {
+			let decoder = zstd::stream::read::Decoder::new(data).map_err(|err| err.to_string())?;
+			let mut value = Vec::new();
+			decoder.take(MAX_VALUE_SIZE as u64 + 1)
+				.read_to_end(&mut value)
+				.map_err(|err| err.to_string())?;
+			if value.len() > MAX_VALUE_SIZE {
+				return Err("value is too large".into());
+			}
+			Ok(value)
+		},
+		flag => Err(format!("unknown compression flag {}", flag)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{compress, decompress, Compression, CompressionConfig, MAX_VALUE_SIZE};
+
+	#[test]
+	fn round_trip() {
+		let value = "orange cat ".repeat(200).into_bytes();
+
+		for compression in &[Compression::None, Compression::Lz4, Compression::Zstd] {
+			let config = CompressionConfig {
+				compression: *compression,
+				threshold: 64,
+			};
+			let record = compress(config, &value);
+			if *compression != Compression::None {
+				assert!(record.len() < value.len());
+			}
+			assert_eq!(decompress(&record).unwrap(), value);
+
+			let small = compress(config, b"cat");
+			assert_eq!(small, b"\0cat".to_vec());
+		}
+
+		assert!(decompress(&[9, 1, 2]).is_err());
+
+		// Sizes claimed by the record are not trusted
+		assert!(decompress(&[1, 0xff, 0xff, 0xff, 0xff, 0]).is_err());
+		let zeros = vec![0; MAX_VALUE_SIZE + 1];
+		let bomb = compress(CompressionConfig { compression: Compression::Zstd, threshold: 0 }, &zeros);
+		assert!(bomb.len() < 64 * 1024);
+		assert!(decompress(&bomb).is_err());
+	}
+}
@@ -0,0 +1,137 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::{convert::TryInto, sync::Arc};
+
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use fnv::FnvHashMap;
+
+// Sealed records share the first byte with the compression flags and manifests
+const SEALED: u8 = 0x53;
+const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = 1 + KEY_ID_LEN + NONCE_LEN;
+
+/// Keys for one prefix. New records are sealed with the active key, while
+/// older keys are kept around to open records written before a rotation.
+#[derive(Clone)]
+pub struct KeySet {
+	active: u32,
+	keys: FnvHashMap<u32, [u8; 32]>,
+}
+
+#[derive(Clone, Default)]
+pub struct Keyring {
+	prefixes: Vec<(String, Arc<KeySet>)>,
+}
+
+impl Keyring {
+	/// Adds a key for `prefix` and makes it the active one.
+	pub fn insert(&mut self, prefix: &str, key_id: u32, key: [u8; 32]) {
+		match self.prefixes.iter_mut().find(|(p, _)| p == prefix) {
+			Some((_, keys)) => {
+				let keys = Arc::make_mut(keys);
+				keys.active = key_id;
+				keys.keys.insert(key_id, key);
+			},
+			None => {
+				let mut keys = FnvHashMap::default();
+				keys.insert(key_id, key);
+				self.prefixes.push((prefix.into(), Arc::new(KeySet {
+					active: key_id,
+					keys,
+				})));
+			},
+		}
+	}
+
+	/// Finds the keys for `key`, preferring the longest matching prefix.
+	pub fn find(&self, key: &str) -> Option<Arc<KeySet>> {
+		self.prefixes.iter()
+			.filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+			.max_by_key(|(prefix, _)| prefix.len())
+			.map(|(_, keys)| keys.clone())
+	}
+}
+
+pub fn is_sealed(record: &[u8]) -> bool {
+	record.first() == Some(&SEALED)
+}
+
+/// Encrypts `record` with the active key. The record key is authenticated too,
+/// so a sealed value cannot be moved to another key without being noticed.
+pub fn seal(keys: &KeySet, key: &str, record: &[u8]) -> Result<Vec<u8>, String> {
+	let secret = keys.keys.get(&keys.active).ok_or("active key is missing")?;
+	let cipher = XChaCha20Poly1305::new(Key::from_slice(secret));
+	let nonce: [u8; NONCE_LEN] = rand::random();
+	let nonce = XNonce::from_slice(&nonce);
+	let payload = Payload {
+		msg: record,
+		aad: key.as_bytes(),
+	};
+	let ciphertext = cipher.encrypt(nonce, payload).map_err(|err| err.to_string())?;
+
+	let mut sealed = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+	sealed.push(SEALED);
+	sealed.extend_from_slice(&keys.active.to_le_bytes());
+	sealed.extend_from_slice(nonce);
+	sealed.extend(ciphertext);
+	Ok(sealed)
+}
+
+pub fn open(keys: &KeySet, key: &str, record: &[u8]) -> Result<Vec<u8>, String> {
+	if record.len() < HEADER_LEN || !is_sealed(record) {
+		return Err("sealed record is truncated".into());
+	}
+
+	let key_id = u32::from_le_bytes(record[1..1 + KEY_ID_LEN].try_into().unwrap_or_default());
+	let secret = match keys.keys.get(&key_id) {
+		Some(secret) => secret,
+		None => return Err(format!("unknown key id {}", key_id)),
+	};
+	let cipher = XChaCha20Poly1305::new(Key::from_slice(secret));
+	let nonce = XNonce::from_slice(&record[1 + KEY_ID_LEN..HEADER_LEN]);
+	let payload = Payload {
+		msg: &record[HEADER_LEN..],
+		aad: key.as_bytes(),
+	};
+	cipher.decrypt(nonce, payload).map_err(|_| "record failed authentication".into())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{is_sealed, open, seal, Keyring};
+
+	#[test]
+	fn seal_and_rotate() {
+		let mut keyring = Keyring::default();
+		keyring.insert("secret:", 1, [7; 32]);
+		assert!(keyring.find("public:cat").is_none());
+
+		let keys = keyring.find("secret:cat").unwrap();
+		let sealed = seal(&keys, "kh-secret:cat-name", b"\0Herbert").unwrap();
+		assert!(is_sealed(&sealed));
+		assert_eq!(open(&keys, "kh-secret:cat-name", &sealed).unwrap(), b"\0Herbert".to_vec());
+		assert!(open(&keys, "kh-secret:cat-color", &sealed).is_err());
+
+		keyring.insert("secret:", 2, [8; 32]);
+		let rotated = keyring.find("secret:cat").unwrap();
+		assert_eq!(open(&rotated, "kh-secret:cat-name", &sealed).unwrap(), b"\0Herbert".to_vec());
+		assert_eq!(&seal(&rotated, "kh-secret:cat-name", b"\0Herbert").unwrap()[1..5], &2u32.to_le_bytes());
+		assert!(open(&rotated, "kh-secret:cat-name", &sealed[..10]).is_err());
+	}
+}
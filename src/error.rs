@@ -0,0 +1,189 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::{error::Error, fmt};
+
+use crate::hash::*;
+use crate::list::*;
+use crate::acl::AclError;
+use crate::node::{FlushError, NodeInitError, ScanError};
+
+/// Kind of failure, stable enough to be mapped onto a wire protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+	NotFound,
+	QuorumFailed,
+	Timeout,
+	OutOfBounds,
+	NotANumber,
+	EmptyList,
+	RankZero,
+	InvalidAddress,
+	DialFailed,
+	Transport,
+	Rejected,
+	Invalid,
+	Encode,
+	Decode,
+	PermissionDenied,
+	Partial,
+}
+
+impl ErrorCode {
+	/// Prefix used for RESP error replies, e.g. `-NOTFOUND ...`.
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			ErrorCode::NotFound => "NOTFOUND",
+			ErrorCode::QuorumFailed => "QUORUM",
+			ErrorCode::Timeout => "TIMEOUT",
+			ErrorCode::OutOfBounds => "OUTOFRANGE",
+			ErrorCode::NotANumber => "NOTANUMBER",
+			ErrorCode::EmptyList => "EMPTY",
+			ErrorCode::RankZero => "RANKZERO",
+			ErrorCode::InvalidAddress => "ADDRESS",
+			ErrorCode::DialFailed => "DIAL",
+			ErrorCode::Transport => "TRANSPORT",
+			ErrorCode::Rejected => "REJECTED",
+			ErrorCode::Invalid => "INVALID",
+			ErrorCode::Encode => "ENCODE",
+			ErrorCode::Decode => "DECODE",
+			ErrorCode::PermissionDenied => "NOPERM",
+			ErrorCode::Partial => "PARTIAL",
+		}
+	}
+
+	pub fn http_status(&self) -> u16 {
+		match self {
+			ErrorCode::NotFound => 404,
+			ErrorCode::QuorumFailed => 503,
+			ErrorCode::Timeout => 504,
+			ErrorCode::OutOfBounds => 416,
+			ErrorCode::NotANumber => 422,
+			ErrorCode::EmptyList => 404,
+			ErrorCode::RankZero => 400,
+			ErrorCode::InvalidAddress => 400,
+			ErrorCode::DialFailed => 502,
+			ErrorCode::Transport => 500,
+			ErrorCode::Rejected => 507,
+			ErrorCode::Invalid => 502,
+			ErrorCode::Encode => 400,
+			ErrorCode::Decode => 422,
+			ErrorCode::PermissionDenied => 403,
+			ErrorCode::Partial => 207,
+		}
+	}
+}
+
+impl fmt::Display for ErrorCode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+/// Implements `code`, `Display`, `Error` and the conversion into `KadisError`
+/// for one of the per-command error enums.
+#[macro_export]
+macro_rules! impl_error {
+	( $error:ident => $kadis:ident { $( $variant:ident { $($field:ident),* } => $code:ident, $msg:literal, )* } ) => {
+		impl $error {
+			pub fn code(&self) -> $crate::ErrorCode {
+				match self {
+					$( $error::$variant { .. } => $crate::ErrorCode::$code, )*
+				}
+			}
+		}
+
+		impl std::fmt::Display for $error {
+			fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+				match self {
+					$( $error::$variant { $($field,)* .. } => write!(f, $msg), )*
+				}
+			}
+		}
+
+		impl std::error::Error for $error {}
+
+		impl From<$error> for $crate::KadisError {
+			fn from(err: $error) -> Self {
+				$crate::KadisError::$kadis(err)
+			}
+		}
+	};
+}
+
+macro_rules! kadis_error {
+	( $( $variant:ident($error:ident), )* ) => {
+		/// Any error returned by Kadis. Every per-command error converts into it,
+		/// so they can be mixed freely with `?`.
+		#[derive(Debug)]
+		pub enum KadisError {
+			$( $variant($error), )*
+		}
+
+		impl KadisError {
+			pub fn code(&self) -> ErrorCode {
+				match self {
+					$( KadisError::$variant(err) => err.code(), )*
+				}
+			}
+		}
+
+		impl fmt::Display for KadisError {
+			fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				match self {
+					$( KadisError::$variant(err) => err.fmt(f), )*
+				}
+			}
+		}
+
+		impl Error for KadisError {
+			fn source(&self) -> Option<&(dyn Error + 'static)> {
+				match self {
+					$( KadisError::$variant(err) => Some(err), )*
+				}
+			}
+		}
+	};
+}
+
+kadis_error! {
+	NodeInit(NodeInitError),
+	Acl(AclError),
+	Flush(FlushError),
+	Scan(ScanError),
+	HDel(HDelError),
+	HExists(HExistsError),
+	HGet(HGetError),
+	HGetAll(HGetAllError),
+	HIncr(HIncrError),
+	HKeys(HKeysError),
+	HLen(HLenError),
+	HScan(HScanError),
+	HSet(HSetError),
+	HVals(HValsError),
+	LCollect(LCollectError),
+	LIndex(LIndexError),
+	LInsert(LInsertError),
+	LLen(LLenError),
+	LPop(LPopError),
+	LPos(LPosError),
+	LPush(LPushError),
+	LRange(LRangeError),
+	LRem(LRemError),
+	LScan(LScanError),
+	LSet(LSetError),
+	LTrim(LTrimError),
+}
@@ -0,0 +1,31 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+
+use crate::codec::{Codec, CodecError};
+
+/// A struct stored as a hash with one field per struct field, usually
+/// implemented with `#[derive(KadisHash)]`.
+pub trait HashFields: Sized {
+	/// Names of the hash fields, in the order values are encoded and decoded.
+	fn fields() -> &'static [&'static str];
+
+	/// Encodes the value of every field in `fields`.
+	fn encode_fields(&self, codec: &(dyn Codec + 'static)) -> Result<Vec<Vec<u8>>, (&'static str, CodecError)>;
+
+	/// Builds the struct from the values of every field in `fields`.
+	fn decode_fields(values: &[Vec<u8>], codec: &(dyn Codec + 'static)) -> Result<Self, (&'static str, CodecError)>;
+}
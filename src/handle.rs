@@ -0,0 +1,214 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+
+use std::{collections::HashMap, marker::PhantomData};
+
+use futures::stream::Stream;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::hash::*;
+use crate::list::*;
+use crate::{Kadis, Scan};
+
+/// A hash whose values are all of type `T`, created with `Kadis::hash`.
+pub struct KadisHash<T> {
+	kadis: Kadis,
+	key: String,
+	value: PhantomData<fn() -> T>,
+}
+
+impl<T> KadisHash<T>
+where T: Serialize + DeserializeOwned {
+	pub(crate) fn new(kadis: Kadis, key: &str) -> Self {
+		Self {
+			kadis,
+			key: key.into(),
+			value: PhantomData,
+		}
+	}
+
+	pub fn key(&self) -> &str {
+		&self.key
+	}
+
+	pub async fn del(&mut self, field: &str) -> Result<(), HDelError> {
+		self.kadis.hdel(&self.key, field).await
+	}
+
+	pub async fn del_multiple(&mut self, fields: &[&str]) -> Result<(), HDelError> {
+		self.kadis.hdel_multiple(&self.key, fields).await
+	}
+
+	pub async fn exists(&mut self, field: &str) -> Result<bool, HExistsError> {
+		self.kadis.hexists(&self.key, field).await
+	}
+
+	pub async fn get(&mut self, field: &str) -> Result<T, HGetError> {
+		self.kadis.hget(&self.key, field).await
+	}
+
+	pub async fn get_multiple(&mut self, fields: &[&str]) -> Result<Vec<T>, HGetError> {
+		self.kadis.hget_multiple(&self.key, fields).await
+	}
+
+	pub async fn get_all(&mut self) -> Result<HashMap<String, T>, HGetAllError> {
+		self.kadis.hgetall(&self.key).await
+	}
+
+	pub async fn keys(&mut self) -> Result<Vec<String>, HKeysError> {
+		self.kadis.hkeys(&self.key).await
+	}
+
+	pub async fn len(&mut self) -> Result<usize, HLenError> {
+		self.kadis.hlen(&self.key).await
+	}
+
+	pub async fn is_empty(&mut self) -> Result<bool, HLenError> {
+		Ok(self.len().await? == 0)
+	}
+
+	pub fn scan<'a>(&'a self, scan: Scan<'a>) -> impl Stream<Item = Result<(String, T), HScanError>> + 'a
+	where T: 'a {
+		self.kadis.hscan(&self.key, scan)
+	}
+
+	pub async fn set(&mut self, field: &str, value: &T) -> Result<(), HSetError> {
+		self.kadis.hset(&self.key, field, value).await
+	}
+
+	pub async fn set_multiple(&mut self, fields: &[&str], values: &[T]) -> Result<(), HSetError> {
+		self.kadis.hset_multiple(&self.key, fields, values).await
+	}
+
+	pub async fn set_nx(&mut self, field: &str, value: &T) -> Result<(), HSetError> {
+		self.kadis.hset_nx(&self.key, field, value).await
+	}
+
+	pub async fn vals(&mut self) -> Result<Vec<T>, HValsError> {
+		self.kadis.hvals(&self.key).await
+	}
+}
+
+// Increments are stored as `f32`, so they are only offered on hashes of them
+impl KadisHash<f32> {
+	pub async fn incr(&mut self, field: &str, inc: u32) -> Result<(), HIncrError> {
+		self.kadis.hincr(&self.key, field, inc).await
+	}
+
+	pub async fn incr_float(&mut self, field: &str, inc: f32) -> Result<(), HIncrError> {
+		self.kadis.hincr_float(&self.key, field, inc).await
+	}
+}
+
+/// A list whose items are all of type `T`, created with `Kadis::list`.
+pub struct KadisList<T> {
+	kadis: Kadis,
+	key: String,
+	item: PhantomData<fn() -> T>,
+}
+
+impl<T> KadisList<T>
+where T: Serialize + DeserializeOwned {
+	pub(crate) fn new(kadis: Kadis, key: &str) -> Self {
+		Self {
+			kadis,
+			key: key.into(),
+			item: PhantomData,
+		}
+	}
+
+	pub fn key(&self) -> &str {
+		&self.key
+	}
+
+	pub async fn collect(&mut self) -> Result<Vec<T>, LCollectError> {
+		self.kadis.lcollect(&self.key).await
+	}
+
+	pub async fn index(&mut self, index: isize) -> Result<T, LIndexError> {
+		self.kadis.lindex(&self.key, index).await
+	}
+
+	pub async fn insert_before(&mut self, index: isize, item: &T) -> Result<(), LInsertError> {
+		self.kadis.linsert_before(&self.key, index, item).await
+	}
+
+	pub async fn insert_after(&mut self, index: isize, item: &T) -> Result<(), LInsertError> {
+		self.kadis.linsert_after(&self.key, index, item).await
+	}
+
+	pub async fn len(&mut self) -> Result<usize, LLenError> {
+		self.kadis.llen(&self.key).await
+	}
+
+	pub async fn is_empty(&mut self) -> Result<bool, LLenError> {
+		Ok(self.len().await? == 0)
+	}
+
+	pub async fn lpop(&mut self) -> Result<T, LPopError> {
+		self.kadis.lpop(&self.key).await
+	}
+
+	pub async fn rpop(&mut self) -> Result<T, LPopError> {
+		self.kadis.rpop(&self.key).await
+	}
+
+	pub async fn pos(&mut self, item: &T) -> Result<Option<usize>, LPosError> {
+		self.kadis.lpos(&self.key, item).await
+	}
+
+	pub async fn pos_rank(&mut self, item: &T, rank: i32) -> Result<Option<usize>, LPosError> {
+		self.kadis.lpos_rank(&self.key, item, rank).await
+	}
+
+	pub async fn lpush(&mut self, item: &T) -> Result<(), LPushError> {
+		self.kadis.lpush(&self.key, item).await
+	}
+
+	pub async fn rpush(&mut self, item: &T) -> Result<(), LPushError> {
+		self.kadis.rpush(&self.key, item).await
+	}
+
+	pub async fn lpush_exists(&mut self, item: &T) -> Result<(), LPushError> {
+		self.kadis.lpush_exists(&self.key, item).await
+	}
+
+	pub async fn rpush_exists(&mut self, item: &T) -> Result<(), LPushError> {
+		self.kadis.rpush_exists(&self.key, item).await
+	}
+
+	pub async fn range(&mut self, start: isize, stop: isize) -> Result<Vec<T>, LRangeError> {
+		self.kadis.lrange(&self.key, start, stop).await
+	}
+
+	pub async fn rem(&mut self, index: isize) -> Result<T, LRemError> {
+		self.kadis.lrem(&self.key, index).await
+	}
+
+	pub fn scan<'a>(&'a self, scan: Scan<'a>) -> impl Stream<Item = Result<T, LScanError>> + 'a
+	where T: 'a {
+		self.kadis.lscan(&self.key, scan)
+	}
+
+	pub async fn set(&mut self, index: isize, item: &T) -> Result<(), LSetError> {
+		self.kadis.lset(&self.key, index, item).await
+	}
+
+	pub async fn trim(&mut self, start: isize, stop: isize) -> Result<(), LTrimError> {
+		self.kadis.ltrim::<T>(&self.key, start, stop).await
+	}
+}
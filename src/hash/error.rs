@@ -14,7 +14,9 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
-use std::collections::HashMap;
+use std::{collections::HashMap, error::Error};
+
+use crate::{impl_error, ErrorCode};
 
 #[derive(Debug)]
 pub enum HDelError {
@@ -27,8 +29,26 @@ pub enum HDelError {
 	KeyTimeout {
 		key: String,
 	},
+	KeyRejected {
+		key: String,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(HDelError => HDel {
+	KeyNotFound { key } => NotFound, "hash `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for hash `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for hash `{key}`",
+	KeyRejected { key } => Rejected, "record for hash `{key}` rejected by local store",
+	KeyInvalid { key } => Invalid, "invalid record for hash `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for hash `{key}`",
+});
+
 #[derive(Debug)]
 pub enum HExistsError {
 	QuorumFailed {
@@ -39,8 +59,22 @@ pub enum HExistsError {
 		key: String,
 		field: String,
 	},
+	Invalid {
+		key: String,
+		field: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(HExistsError => HExists {
+	QuorumFailed { key, field } => QuorumFailed, "quorum failed for field `{field}` of hash `{key}`",
+	Timeout { key, field } => Timeout, "request timed out for field `{field}` of hash `{key}`",
+	Invalid { key, field } => Invalid, "invalid record for field `{field}` of hash `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for hash `{key}`",
+});
+
 #[derive(Debug)]
 pub enum HGetError {
 	NotFound {
@@ -55,8 +89,29 @@ pub enum HGetError {
 		key: String,
 		field: String,
 	},
+	Decode {
+		key: String,
+		field: String,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	Invalid {
+		key: String,
+		field: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(HGetError => HGet {
+	NotFound { key, field } => NotFound, "field `{field}` not found in hash `{key}`",
+	QuorumFailed { key, field } => QuorumFailed, "quorum failed for field `{field}` of hash `{key}`",
+	Timeout { key, field } => Timeout, "request timed out for field `{field}` of hash `{key}`",
+	Decode { key, field, cause } => Decode, "could not decode field `{field}` of hash `{key}`: {cause}",
+	Invalid { key, field } => Invalid, "invalid record for field `{field}` of hash `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for hash `{key}`",
+});
+
 #[derive(Debug)]
 pub enum HGetAllError {
 	KeyNotFound {
@@ -80,8 +135,41 @@ pub enum HGetAllError {
 		key: String,
 		field: String,
 	},
+	Decode {
+		key: String,
+		field: String,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	Invalid {
+		key: String,
+		field: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
+	Partial {
+		key: String,
+		errors: Vec<HGetAllError>,
+	},
 }
 
+impl_error!(HGetAllError => HGetAll {
+	KeyNotFound { key } => NotFound, "hash `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for hash `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for hash `{key}`",
+	NotFound { key, field } => NotFound, "field `{field}` not found in hash `{key}`",
+	QuorumFailed { key, field } => QuorumFailed, "quorum failed for field `{field}` of hash `{key}`",
+	Timeout { key, field } => Timeout, "request timed out for field `{field}` of hash `{key}`",
+	Decode { key, field, cause } => Decode, "could not decode field `{field}` of hash `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for hash `{key}`",
+	Invalid { key, field } => Invalid, "invalid record for field `{field}` of hash `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for hash `{key}`",
+	Partial { key } => Partial, "some fields of hash `{key}` could not be read",
+});
+
 #[derive(Debug)]
 pub enum HIncrError {
 	NotFound {
@@ -100,8 +188,29 @@ pub enum HIncrError {
 		key: String,
 		value: Vec<u8>,
 	},
+	Rejected {
+		key: String,
+		field: String,
+	},
+	Invalid {
+		key: String,
+		field: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(HIncrError => HIncr {
+	NotFound { key, field } => NotFound, "field `{field}` not found in hash `{key}`",
+	QuorumFailed { key, field } => QuorumFailed, "quorum failed for field `{field}` of hash `{key}`",
+	Timeout { key, field } => Timeout, "request timed out for field `{field}` of hash `{key}`",
+	NotANumber { key } => NotANumber, "value in hash `{key}` is not a number",
+	Rejected { key, field } => Rejected, "record for field `{field}` of hash `{key}` rejected by local store",
+	Invalid { key, field } => Invalid, "invalid record for field `{field}` of hash `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for hash `{key}`",
+});
+
 #[derive(Debug)]
 pub enum HKeysError {
 	KeyNotFound {
@@ -113,8 +222,22 @@ pub enum HKeysError {
 	KeyTimeout {
 		key: String,
 	},
+	KeyInvalid {
+		key: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(HKeysError => HKeys {
+	KeyNotFound { key } => NotFound, "hash `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for hash `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for hash `{key}`",
+	KeyInvalid { key } => Invalid, "invalid record for hash `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for hash `{key}`",
+});
+
 #[derive(Debug)]
 pub enum HLenError {
 	KeyNotFound {
@@ -126,8 +249,22 @@ pub enum HLenError {
 	KeyTimeout {
 		key: String,
 	},
+	KeyInvalid {
+		key: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(HLenError => HLen {
+	KeyNotFound { key } => NotFound, "hash `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for hash `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for hash `{key}`",
+	KeyInvalid { key } => Invalid, "invalid record for hash `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for hash `{key}`",
+});
+
 #[derive(Debug)]
 pub enum HSetError {
 	QuorumFailed {
@@ -144,8 +281,44 @@ pub enum HSetError {
 	KeyTimeout {
 		key: String,
 	},
+	Rejected {
+		key: String,
+		field: String,
+	},
+	KeyRejected {
+		key: String,
+	},
+	Encode {
+		key: String,
+		field: String,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
+	LengthMismatch {
+		key: String,
+		fields: usize,
+		values: usize,
+	},
 }
 
+impl_error!(HSetError => HSet {
+	QuorumFailed { key, field } => QuorumFailed, "quorum failed for field `{field}` of hash `{key}`",
+	Timeout { key, field } => Timeout, "request timed out for field `{field}` of hash `{key}`",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for hash `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for hash `{key}`",
+	Rejected { key, field } => Rejected, "record for field `{field}` of hash `{key}` rejected by local store",
+	KeyRejected { key } => Rejected, "record for hash `{key}` rejected by local store",
+	Encode { key, field, cause } => Encode, "could not encode field `{field}` of hash `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for hash `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for hash `{key}`",
+	LengthMismatch { key, fields, values } => Invalid, "{fields} fields but {values} values given for hash `{key}`",
+});
+
 #[derive(Debug)]
 pub enum HValsError {
 	KeyNotFound {
@@ -169,8 +342,102 @@ pub enum HValsError {
 		key: String,
 		field: String,
 	},
+	Decode {
+		key: String,
+		field: String,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	Invalid {
+		key: String,
+		field: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
+	Partial {
+		key: String,
+		errors: Vec<HValsError>,
+	},
 }
 
+impl_error!(HValsError => HVals {
+	KeyNotFound { key } => NotFound, "hash `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for hash `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for hash `{key}`",
+	NotFound { key, field } => NotFound, "field `{field}` not found in hash `{key}`",
+	QuorumFailed { key, field } => QuorumFailed, "quorum failed for field `{field}` of hash `{key}`",
+	Timeout { key, field } => Timeout, "request timed out for field `{field}` of hash `{key}`",
+	Decode { key, field, cause } => Decode, "could not decode field `{field}` of hash `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for hash `{key}`",
+	Invalid { key, field } => Invalid, "invalid record for field `{field}` of hash `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for hash `{key}`",
+	Partial { key } => Partial, "some fields of hash `{key}` could not be read",
+});
+
+#[derive(Debug)]
+pub enum HScanError {
+	KeyNotFound {
+		key: String,
+	},
+	KeyQuorumFailed {
+		key: String,
+	},
+	KeyTimeout {
+		key: String,
+	},
+	NotFound {
+		key: String,
+		field: String,
+	},
+	QuorumFailed {
+		key: String,
+		field: String,
+	},
+	Timeout {
+		key: String,
+		field: String,
+	},
+	Decode {
+		key: String,
+		field: String,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	Invalid {
+		key: String,
+		field: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
+	Partial {
+		key: String,
+		errors: Vec<HScanError>,
+	},
+}
+
+impl_error!(HScanError => HScan {
+	KeyNotFound { key } => NotFound, "hash `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for hash `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for hash `{key}`",
+	NotFound { key, field } => NotFound, "field `{field}` not found in hash `{key}`",
+	QuorumFailed { key, field } => QuorumFailed, "quorum failed for field `{field}` of hash `{key}`",
+	Timeout { key, field } => Timeout, "request timed out for field `{field}` of hash `{key}`",
+	Decode { key, field, cause } => Decode, "could not decode field `{field}` of hash `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for hash `{key}`",
+	Invalid { key, field } => Invalid, "invalid record for field `{field}` of hash `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for hash `{key}`",
+	Partial { key } => Partial, "some fields of hash `{key}` could not be read",
+});
+
+/// Fields read by one page of a hash scan, and the cursor of the next page.
+pub type HashPage = (usize, Vec<(String, Vec<u8>)>);
+
 pub enum HashResult {
 	Del(Result<(), HDelError>),
 	Exists(Result<bool, HExistsError>),
@@ -180,8 +447,30 @@ pub enum HashResult {
 	Incr(Result<(), HIncrError>),
 	Keys(Result<Vec<String>, HKeysError>),
 	Len(Result<usize, HLenError>),
+	Scan(Result<HashPage, HScanError>),
 	Set(Result<(), HSetError>),
 	SetM(Result<(), HSetError>),
 	SetNx(Result<(), HSetError>),
-	Vals(Result<Vec<Vec<u8>>, HValsError>),
+	Vals(Result<Vec<(String, Vec<u8>)>, HValsError>),
+}
+
+impl HashResult {
+	/// Code of the error the command failed with, if it did.
+	pub fn code(&self) -> Option<ErrorCode> {
+		match self {
+			HashResult::Del(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::Exists(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::Get(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::GetM(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::GetAll(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::Incr(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::Keys(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::Len(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::Scan(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::Set(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::SetM(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::SetNx(res) => res.as_ref().err().map(|err| err.code()),
+			HashResult::Vals(res) => res.as_ref().err().map(|err| err.code()),
+		}
+	}
 }
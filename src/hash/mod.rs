@@ -19,9 +19,14 @@ use std::{
 	str,
 };
 
-use crate::{get_list, get_list_exists, join_list};
+use tracing::{field, Instrument};
+
+use crate::{codec, get_list, get_list_exists, join_list};
+use crate::acl::Permission;
+use crate::keys;
+use crate::scan::glob_match;
 use crate::node::{Node, GetError, PutError};
-use crate::util::split_list;
+use crate::util::{decode_list, encode_list};
 
 mod error;
 #[cfg(test)]
@@ -38,6 +43,7 @@ pub enum HashCmd<'a> {
 	Incr(&'a str, &'a str, f32),
 	Keys(&'a str),
 	Len(&'a str),
+	Scan(&'a str, usize, usize, Option<&'a str>),
 	Set(&'a str, &'a str, Vec<u8>),
 	SetM(&'a str, &'a [&'a str], Vec<Vec<u8>>),
 	SetNx(&'a str, &'a str, Vec<u8>),
@@ -46,15 +52,115 @@ pub enum HashCmd<'a> {
 
 use HashCmd::*;
 
+impl HashCmd<'_> {
+	pub fn key(&self) -> &str {
+		match self {
+			Del(key, ..) => key,
+			Exists(key, ..) => key,
+			Get(key, ..) => key,
+			GetM(key, ..) => key,
+			GetAll(key) => key,
+			Incr(key, ..) => key,
+			Keys(key) => key,
+			Len(key) => key,
+			Scan(key, ..) => key,
+			Set(key, ..) => key,
+			SetM(key, ..) => key,
+			SetNx(key, ..) => key,
+			Vals(key) => key,
+		}
+	}
+
+	/// Name of the command, as used by the `Kadis` method running it.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Del(..) => "hdel",
+			Exists(..) => "hexists",
+			Get(..) => "hget",
+			GetM(..) => "hget_multiple",
+			GetAll(..) => "hgetall",
+			Incr(..) => "hincr",
+			Keys(..) => "hkeys",
+			Len(..) => "hlen",
+			Scan(..) => "hscan",
+			Set(..) => "hset",
+			SetM(..) => "hset_multiple",
+			SetNx(..) => "hset_nx",
+			Vals(..) => "hvals",
+		}
+	}
+
+	pub fn permission(&self) -> Permission {
+		match self {
+			Del(..) => Permission::Delete,
+			Exists(..) => Permission::Read,
+			Get(..) => Permission::Read,
+			GetM(..) => Permission::Read,
+			GetAll(..) => Permission::Read,
+			Incr(..) => Permission::Write,
+			Keys(..) => Permission::Read,
+			Len(..) => Permission::Read,
+			Scan(..) => Permission::Read,
+			Set(..) => Permission::Write,
+			SetM(..) => Permission::Write,
+			SetNx(..) => Permission::Write,
+			Vals(..) => Permission::Read,
+		}
+	}
+}
+
+// Numbers are written back with whichever built-in codec stored them
+fn incr(value: &[u8], inc: f32) -> Option<Vec<u8>> {
+	let codec = codec::builtin(*value.first()?)?;
+	let number = codec.decode::<f32>(value).ok()?;
+	codec.encode(&(number + inc)).ok()
+}
+
 pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
+	let span = tracing::info_span!(
+		"hash",
+		command = cmd.name(),
+		key = cmd.key(),
+		records = field::Empty,
+		outcome = field::Empty,
+	);
+	node.start_command();
+
+	let res = run_hash_cmd(node, cmd).instrument(span.clone()).await;
+
+	span.record("records", node.touched());
+	span.record("outcome", res.code().map_or("ok", |code| code.as_str()));
+	res
+}
+
+async fn run_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
+	if !node.permits(cmd.key(), cmd.permission()) {
+		let key = cmd.key().into();
+		return match cmd {
+			Del(..) => HashResult::Del(Err(HDelError::PermissionDenied { key })),
+			Exists(..) => HashResult::Exists(Err(HExistsError::PermissionDenied { key })),
+			Get(..) => HashResult::Get(Err(HGetError::PermissionDenied { key })),
+			GetM(..) => HashResult::GetM(Err(HGetError::PermissionDenied { key })),
+			GetAll(..) => HashResult::GetAll(Err(HGetAllError::PermissionDenied { key })),
+			Incr(..) => HashResult::Incr(Err(HIncrError::PermissionDenied { key })),
+			Keys(..) => HashResult::Keys(Err(HKeysError::PermissionDenied { key })),
+			Len(..) => HashResult::Len(Err(HLenError::PermissionDenied { key })),
+			Scan(..) => HashResult::Scan(Err(HScanError::PermissionDenied { key })),
+			Set(..) => HashResult::Set(Err(HSetError::PermissionDenied { key })),
+			SetM(..) => HashResult::SetM(Err(HSetError::PermissionDenied { key })),
+			SetNx(..) => HashResult::SetNx(Err(HSetError::PermissionDenied { key })),
+			Vals(..) => HashResult::Vals(Err(HValsError::PermissionDenied { key })),
+		};
+	}
+
 	match cmd {
 		Del(key, fields) => {
 			for field in fields {
-				let key = format!("kh-{}-{}", key, field);
-				node.remove(&key);
+				let key = keys::hash_field(key, field);
+				node.remove(&key).await;
 			}
 			
-			let fields_key = format!("kh-fields-{}", key);
+			let fields_key = keys::hash_fields(key);
 			let hash_fields = get_list!(node, fields_key, HashResult, Del, HDelError);
 
 			let hash_fields = hash_fields.iter()
@@ -67,9 +173,9 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 			HashResult::Del(Ok(()))
 		},
 		Exists(key, field) => {
-			let fields_key = format!("kh-fields-{}", key);
-			let fields = match node.get(&fields_key).await {
-				Ok(fields) => split_list(fields),
+			let fields_key = keys::hash_fields(key);
+			let fields = match node.get(&fields_key).await.and_then(decode_list) {
+				Ok(fields) => fields,
 				Err(err) => return match err {
 					GetError::NotFound => HashResult::Exists(Ok(false)),
 					GetError::QuorumFailed => HashResult::Exists(Err(HExistsError::QuorumFailed {
@@ -80,13 +186,17 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 						key: key.into(),
 						field: field.into(),
 					})),
+					GetError::Invalid => HashResult::Exists(Err(HExistsError::Invalid {
+						key: key.into(),
+						field: field.into(),
+					})),
 				},
 			};
 			let exists = fields.contains(&field.into());
 			HashResult::Exists(Ok(exists))
 		},
 		Get(key, field) => {
-			let hash_key = format!("kh-{}-{}", key, field);
+			let hash_key = keys::hash_field(key, field);
 			match node.get(&hash_key).await {
 				Ok(data) => HashResult::Get(Ok(data)),
 				Err(err) => match err {
@@ -102,6 +212,10 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 						key: key.into(),
 						field: field.into(),
 					})),
+					GetError::Invalid => HashResult::Get(Err(HGetError::Invalid {
+						key: key.into(),
+						field: field.into(),
+					})),
 				},
 			}
 		},
@@ -109,7 +223,7 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 			let mut values = Vec::new();
 
 			for field in fields {
-				let hash_key = format!("kh-{}-{}", key, field);
+				let hash_key = keys::hash_field(key, field);
 				let value = match node.get(&hash_key).await {
 					Ok(data) => data,
 					Err(err) => return match err {
@@ -125,6 +239,10 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 							key: key.into(),
 							field: (*field).into(),
 						})),
+						GetError::Invalid => HashResult::GetM(Err(HGetError::Invalid {
+							key: key.into(),
+							field: (*field).into(),
+						})),
 					},
 				};
 				values.push(value);
@@ -133,37 +251,49 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 			HashResult::GetM(Ok(values))
 		},
 		GetAll(key) => {
-			let fields_key = format!("kh-fields-{}", key);
+			let fields_key = keys::hash_fields(key);
 			let fields = get_list!(node, fields_key, HashResult, GetAll, HGetAllError);
 
 			let mut values = HashMap::new();
 
-			for field in fields {
-				let hash_key = format!("kh-{}-{}", key, field);
-				let value = match node.get(&hash_key).await {
-					Ok(data) => data,
-					Err(err) => return match err {
-						GetError::NotFound => HashResult::GetAll(Err(HGetAllError::NotFound {
+			let record_keys: Vec<_> = fields.iter().map(|field| keys::hash_field(key, field)).collect();
+			let mut errors = Vec::new();
+
+			for (field, res) in fields.into_iter().zip(node.get_many(&record_keys).await) {
+				match res {
+					Ok(data) => { values.insert(field, data); },
+					Err(err) => errors.push(match err {
+						GetError::NotFound => HGetAllError::NotFound {
 							key: key.into(),
 							field,
-						})),
-						GetError::QuorumFailed => HashResult::GetAll(Err(HGetAllError::QuorumFailed {
+						},
+						GetError::QuorumFailed => HGetAllError::QuorumFailed {
 							key: key.into(),
 							field,
-						})),
-						GetError::Timeout => HashResult::GetAll(Err(HGetAllError::Timeout {
+						},
+						GetError::Timeout => HGetAllError::Timeout {
 							key: key.into(),
 							field,
-						})),
-					},
+						},
+						GetError::Invalid => HGetAllError::Invalid {
+							key: key.into(),
+							field,
+						},
+					}),
 				};
-				values.insert(field, value);
+			}
+
+			if !errors.is_empty() {
+				return HashResult::GetAll(Err(HGetAllError::Partial {
+					key: key.into(),
+					errors,
+				}));
 			}
 
 			HashResult::GetAll(Ok(values))
 		},
 		Incr(key, field, inc) => {
-			let hash_key = format!("kh-{}-{}", key, field);
+			let hash_key = keys::hash_field(key, field);
 			let value = match node.get(&hash_key).await {
 				Ok(data) => data,
 				Err(err) => return match err {
@@ -179,17 +309,20 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 						key: key.into(),
 						field: field.into(),
 					})),
+					GetError::Invalid => HashResult::Incr(Err(HIncrError::Invalid {
+						key: key.into(),
+						field: field.into(),
+					})),
 				},
 			};
 
-			let value = match bincode::deserialize::<f32>(&value) {
-				Ok(value) => value + inc,
-				Err(_) => return HashResult::Incr(Err(HIncrError::NotANumber {
+			let value = match incr(&value, inc) {
+				Some(value) => value,
+				None => return HashResult::Incr(Err(HIncrError::NotANumber {
 					key: key.into(),
 					value,
 				})),
 			};
-			let value = bincode::serialize(&value).unwrap();
 
 			match node.put(&hash_key, value).await {
 				Ok(()) => HashResult::Incr(Ok(())),
@@ -202,26 +335,88 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 						key: key.into(),
 						field: field.into(),
 					})),
+					PutError::Rejected => HashResult::Incr(Err(HIncrError::Rejected {
+						key: key.into(),
+						field: field.into(),
+					})),
 				}
 			}
 		},
 		Keys(key) => {
-			let fields_key = format!("kh-fields-{}", key);
+			let fields_key = keys::hash_fields(key);
 			let keys = get_list!(node, fields_key, HashResult, Keys, HKeysError);
 			HashResult::Keys(Ok(keys))
 		},
 		Len(key) => {
-			let fields_key = format!("kh-fields-{}", key);
+			let fields_key = keys::hash_fields(key);
 			let fields = get_list!(node, fields_key, HashResult, Len, HLenError);
 			HashResult::Len(Ok(fields.len()))
 		},
+		Scan(key, cursor, count, pattern) => {
+			let fields_key = keys::hash_fields(key);
+			let fields = get_list!(node, fields_key, HashResult, Scan, HScanError);
+			let len = fields.len();
+
+			let start = cursor.min(len);
+			let end = start.saturating_add(count.max(1)).min(len);
+			let fields: Vec<String> = fields[start..end].iter()
+				.filter(|field| match pattern {
+					Some(pattern) => glob_match(pattern, field),
+					None => true,
+				})
+				.cloned()
+				.collect();
+
+			// Like Redis, a cursor of 0 means the whole hash has been read
+			let next = if end < len { end } else { 0 };
+
+			let mut values = Vec::new();
+
+			let record_keys: Vec<_> = fields.iter().map(|field| keys::hash_field(key, field)).collect();
+			let mut errors = Vec::new();
+
+			for (field, res) in fields.into_iter().zip(node.get_many(&record_keys).await) {
+				match res {
+					Ok(data) => values.push((field, data)),
+					Err(err) => errors.push(match err {
+						GetError::NotFound => HScanError::NotFound {
+							key: key.into(),
+							field,
+						},
+						GetError::QuorumFailed => HScanError::QuorumFailed {
+							key: key.into(),
+							field,
+						},
+						GetError::Timeout => HScanError::Timeout {
+							key: key.into(),
+							field,
+						},
+						GetError::Invalid => HScanError::Invalid {
+							key: key.into(),
+							field,
+						},
+					}),
+				};
+			}
+
+			if !errors.is_empty() {
+				return HashResult::Scan(Err(HScanError::Partial {
+					key: key.into(),
+					errors,
+				}));
+			}
+
+			HashResult::Scan(Ok((next, values)))
+		},
 		Set(key, field, value) => {
-			let fields_key = format!("kh-fields-{}", key);
+			let fields_key = keys::hash_fields(key);
 			let mut hash_fields = get_list_exists!(node, fields_key, HashResult, Set, HSetError);
 
-			let hash_key = format!("kh-{}-{}", key, field);
+			let hash_key = keys::hash_field(key, field);
 			match node.put(&hash_key, value).await {
-				Ok(()) => hash_fields.push(field.into()),
+				Ok(()) => if !hash_fields.iter().any(|f| f == field) {
+					hash_fields.push(field.into());
+				},
 				Err(err) => return match err {
 					PutError::QuorumFailed => HashResult::Set(Err(HSetError::QuorumFailed {
 						key: key.into(),
@@ -231,6 +426,10 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 						key: key.into(),
 						field: field.into(),
 					})),
+					PutError::Rejected => HashResult::Set(Err(HSetError::Rejected {
+						key: key.into(),
+						field: field.into(),
+					})),
 				}
 			}
 
@@ -239,15 +438,25 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 			HashResult::Set(Ok(()))
 		},
 		SetM(key, fields, values) => {
-			let fields_key = format!("kh-fields-{}", key);
+			if fields.len() != values.len() {
+				return HashResult::SetM(Err(HSetError::LengthMismatch {
+					key: key.into(),
+					fields: fields.len(),
+					values: values.len(),
+				}));
+			}
+
+			let fields_key = keys::hash_fields(key);
 			let mut hash_fields = get_list_exists!(node, fields_key, HashResult, SetM, HSetError);
 
 			for i in 0..fields.len() {
 				let field = fields[i];
 				let value = values[i].clone();
-				let hash_key = format!("kh-{}-{}", key, field);
+				let hash_key = keys::hash_field(key, field);
 				match node.put(&hash_key, value).await {
-					Ok(()) => hash_fields.push(field.into()),
+					Ok(()) => if !hash_fields.iter().any(|f| f == field) {
+						hash_fields.push(field.into());
+					},
 					Err(err) => return match err {
 						PutError::QuorumFailed => HashResult::SetM(Err(HSetError::QuorumFailed {
 							key: key.into(),
@@ -257,6 +466,10 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 							key: key.into(),
 							field: field.into(),
 						})),
+						PutError::Rejected => HashResult::SetM(Err(HSetError::Rejected {
+							key: key.into(),
+							field: field.into(),
+						})),
 					}
 				}
 			}
@@ -266,12 +479,12 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 			HashResult::SetM(Ok(()))
 		},
 		SetNx(key, field, value) => {
-			let fields_key = format!("kh-fields-{}", key);
+			let fields_key = keys::hash_fields(key);
 			let mut hash_fields = get_list_exists!(node, fields_key, HashResult, SetNx, HSetError);
 			let exists = hash_fields.contains(&field.into());
 
 			if !exists {
-				let hash_key = format!("kh-{}-{}", key, field);
+				let hash_key = keys::hash_field(key, field);
 				match node.put(&hash_key, value).await {
 					Ok(()) => hash_fields.push(field.into()),
 					Err(err) => return match err {
@@ -283,6 +496,10 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 							key: key.into(),
 							field: field.into(),
 						})),
+						PutError::Rejected => HashResult::SetNx(Err(HSetError::Rejected {
+							key: key.into(),
+							field: field.into(),
+						})),
 					}
 				}
 
@@ -292,31 +509,43 @@ pub async fn handle_hash_cmd(node: &mut Node, cmd: HashCmd<'_>) -> HashResult {
 			HashResult::SetNx(Ok(()))
 		},
 		Vals(key) => {
-			let fields_key = format!("kh-fields-{}", key);
+			let fields_key = keys::hash_fields(key);
 			let fields = get_list!(node, fields_key, HashResult, Vals, HValsError);
 
 			let mut values = Vec::new();
 
-			for field in fields {
-				let hash_key = format!("kh-{}-{}", key, field);
-				let value = match node.get(&hash_key).await {
-					Ok(data) => data,
-					Err(err) => return match err {
-						GetError::NotFound => HashResult::Vals(Err(HValsError::NotFound {
+			let record_keys: Vec<_> = fields.iter().map(|field| keys::hash_field(key, field)).collect();
+			let mut errors = Vec::new();
+
+			for (field, res) in fields.into_iter().zip(node.get_many(&record_keys).await) {
+				match res {
+					Ok(data) => values.push((field, data)),
+					Err(err) => errors.push(match err {
+						GetError::NotFound => HValsError::NotFound {
 							key: key.into(),
 							field,
-						})),
-						GetError::QuorumFailed => HashResult::Vals(Err(HValsError::QuorumFailed {
+						},
+						GetError::QuorumFailed => HValsError::QuorumFailed {
 							key: key.into(),
 							field,
-						})),
-						GetError::Timeout => HashResult::Vals(Err(HValsError::Timeout {
+						},
+						GetError::Timeout => HValsError::Timeout {
 							key: key.into(),
 							field,
-						})),
-					},
+						},
+						GetError::Invalid => HValsError::Invalid {
+							key: key.into(),
+							field,
+						},
+					}),
 				};
-				values.push(value);
+			}
+
+			if !errors.is_empty() {
+				return HashResult::Vals(Err(HValsError::Partial {
+					key: key.into(),
+					errors,
+				}));
 			}
 
 			HashResult::Vals(Ok(values))
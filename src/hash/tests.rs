@@ -14,10 +14,14 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
+use std::collections::HashMap;
+
 use async_std::task;
+use futures::StreamExt;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
-use crate::KadisBuilder;
+use crate::{ErrorCode, Kadis, KadisBuilder, Reply, Scan};
 
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 struct Cat {
@@ -27,7 +31,7 @@ struct Cat {
 
 #[test]
 fn hash() {
-	let mut kadis = KadisBuilder::default().bootstraps(&["/ip4/0.0.0.0/tcp/5130"]).init().unwrap();
+	let mut kadis = KadisBuilder::local().init().unwrap();
 
 	task::block_on(async move {
 		let res = kadis.hexists("cats", "herb").await;
@@ -124,5 +128,267 @@ fn hash() {
 		let res = kadis.hvals::<f32>("nums").await;
 		assert!(res.is_ok());
 		assert_eq!(res.unwrap(), vec![8.0, 5.0, 12.0]);
+
+		let mut pipeline = kadis.pipeline();
+		pipeline
+			.hset("pets", "herb", &cat)
+			.hincr("nums", "n1", 1)
+			.hget("pets", "herb")
+			.hlen("nums");
+		let res = pipeline.exec().await;
+		assert_eq!(res.len(), 4);
+		assert!(matches!(res[0], Ok(Reply::Ok)));
+		assert!(matches!(res[1], Ok(Reply::Ok)));
+		match &res[2] {
+			Ok(Reply::Value(value)) => assert_eq!(value.decode::<Cat>().unwrap(), cat),
+			_ => panic!("expected a value"),
+		}
+		assert!(matches!(res[3], Ok(Reply::Len(3))));
+
+		let res: Vec<_> = kadis.hscan::<f32>("nums", Scan::new().count(2).pattern("n[12]")).collect().await;
+		let res: Result<Vec<_>, _> = res.into_iter().collect();
+		assert!(res.is_ok());
+		assert_eq!(res.unwrap(), vec![("n1".to_string(), 9.0), ("n2".to_string(), 12.0)]);
 	})
 }
+
+const FIELDS: &[&str] = &["a", "b", "c", "d"];
+
+// Reference model of a hash in field insertion order, `None` until the first set
+struct Model(Option<Vec<(String, f32)>>);
+
+impl Model {
+	fn hash(&self) -> Result<&Vec<(String, f32)>, ErrorCode> {
+		self.0.as_ref().ok_or(ErrorCode::NotFound)
+	}
+
+	fn get(&self, field: &str) -> Result<f32, ErrorCode> {
+		self.0.iter().flatten()
+			.find(|(f, _)| f == field)
+			.map(|(_, value)| *value)
+			.ok_or(ErrorCode::NotFound)
+	}
+
+	fn set(&mut self, field: &str, value: f32) {
+		let hash = self.0.get_or_insert_with(Vec::new);
+		match hash.iter_mut().find(|(f, _)| f == field) {
+			Some(entry) => entry.1 = value,
+			None => hash.push((field.into(), value)),
+		}
+	}
+}
+
+async fn step(kadis: &mut Kadis, model: &mut Model, rng: &mut StdRng, key: &str) {
+	let field = *FIELDS.choose(rng).unwrap();
+	let other = *FIELDS.choose(rng).unwrap();
+	let value = rng.gen_range(0, 8) as f32;
+
+	match rng.gen_range(0, 11) {
+		0 => {
+			assert!(kadis.hset(key, field, value).await.is_ok());
+			model.set(field, value);
+		},
+		1 => {
+			assert!(kadis.hset_nx(key, field, value).await.is_ok());
+			if model.get(field).is_err() {
+				model.set(field, value);
+			}
+		},
+		2 => {
+			let values = [value, value + 1.0];
+			assert!(kadis.hset_multiple(key, &[field, other], &values).await.is_ok());
+			model.set(field, values[0]);
+			model.set(other, values[1]);
+		},
+		3 => {
+			let res = kadis.hdel(key, field).await;
+			let expected = model.hash().map(|_| ());
+			if let Some(hash) = model.0.as_mut() {
+				hash.retain(|(f, _)| f != field);
+			}
+			assert_eq!(res.map_err(|err| err.code()), expected, "hdel {}", field);
+		},
+		4 => {
+			let res = kadis.hget::<f32>(key, field).await;
+			assert_eq!(res.map_err(|err| err.code()), model.get(field), "hget {}", field);
+		},
+		5 => {
+			let res = kadis.hexists(key, field).await;
+			assert_eq!(res.map_err(|err| err.code()), Ok(model.get(field).is_ok()), "hexists {}", field);
+		},
+		6 => {
+			let res = kadis.hincr_float(key, field, value).await;
+			let expected = model.get(field).map(|old| model.set(field, old + value));
+			assert_eq!(res.map_err(|err| err.code()), expected, "hincr {}", field);
+		},
+		7 => {
+			let res = kadis.hkeys(key).await;
+			let expected = model.hash().map(|hash| hash.iter().map(|(f, _)| f.clone()).collect());
+			assert_eq!(res.map_err(|err| err.code()), expected, "hkeys");
+		},
+		8 => {
+			let res = kadis.hlen(key).await;
+			assert_eq!(res.map_err(|err| err.code()), model.hash().map(Vec::len), "hlen");
+		},
+		9 => {
+			let res = kadis.hvals::<f32>(key).await;
+			let expected = model.hash().map(|hash| hash.iter().map(|(_, value)| *value).collect());
+			assert_eq!(res.map_err(|err| err.code()), expected, "hvals");
+		},
+		_ => {
+			let res = kadis.hgetall::<f32>(key).await;
+			let expected = model.hash().map(|hash| hash.iter().cloned().collect::<HashMap<_, _>>());
+			assert_eq!(res.map_err(|err| err.code()), expected, "hgetall");
+		},
+	}
+}
+
+#[test]
+fn model() {
+	let mut kadis = KadisBuilder::local().init().unwrap();
+
+	task::block_on(async move {
+		for seed in 0..20 {
+			let key = format!("model-{}", seed);
+			let mut rng = StdRng::seed_from_u64(seed);
+			let mut model = Model(None);
+
+			for _ in 0..100 {
+				step(&mut kadis, &mut model, &mut rng, &key).await;
+			}
+		}
+	});
+}
+
+#[test]
+fn touched() {
+	let mut kadis = KadisBuilder::local().init().unwrap();
+
+	task::block_on(async move {
+		let res = kadis.hset_multiple("cats", &["herb", "ferb", "kirby"], &[1u32, 2, 3]).await;
+		assert!(res.is_ok());
+
+		// The field list and each of the three fields
+		let res = kadis.hgetall::<u32>("cats").await;
+		assert!(res.is_ok());
+		assert_eq!(kadis.node.touched(), 4);
+	});
+}
+
+#[test]
+fn length_mismatch() {
+	let mut kadis = KadisBuilder::local().init().unwrap();
+
+	task::block_on(async move {
+		let res = kadis.hset_multiple("cats", &["herb", "ferb"], &[1u32]).await;
+		assert_eq!(res.map_err(|err| err.code()), Err(ErrorCode::Invalid));
+
+		let res = kadis.hset_multiple("cats", &["herb"], &[1u32, 2]).await;
+		assert_eq!(res.map_err(|err| err.code()), Err(ErrorCode::Invalid));
+
+		let res = kadis.hset_multiple_bytes("cats", &["herb", "ferb"], &[b"1"]).await;
+		assert_eq!(res.map_err(|err| err.code()), Err(ErrorCode::Invalid));
+
+		let mut pipeline = kadis.pipeline();
+		pipeline
+			.hset_multiple("cats", &["herb", "ferb"], &[1u32])
+			.hset_multiple_bytes("cats", &["herb"], &[b"1", b"2"]);
+		let res = pipeline.exec().await;
+		assert!(res.iter().all(|res| matches!(res, Err(err) if err.code() == ErrorCode::Invalid)));
+
+		// Nothing was written
+		let res = kadis.hlen("cats").await;
+		assert_eq!(res.map_err(|err| err.code()), Err(ErrorCode::NotFound));
+	});
+}
+
+#[test]
+fn legacy() {
+	let mut kadis = KadisBuilder::local().init().unwrap();
+
+	let cat = Cat {
+		name: "Herbert".into(),
+		color: "orange".into(),
+	};
+	// 0x50 long, so the value starts with the byte signed records start with
+	let name = "P".repeat(0x50);
+	kadis.node.put_legacy("kh-cats-herb", &bincode::serialize(&cat).unwrap());
+	kadis.node.put_legacy("kh-cats-Pip", &bincode::serialize(&Cat {
+		name: name.clone(),
+		color: "grey".into(),
+	}).unwrap());
+	kadis.node.put_legacy("kh-fields-cats", b"Pip,herb");
+
+	task::block_on(async move {
+		let res = kadis.hget::<Cat>("cats", "herb").await;
+		assert_eq!(res.unwrap(), cat);
+
+		let res = kadis.hgetall::<Cat>("cats").await;
+		let cats = res.unwrap();
+		assert_eq!(cats.len(), 2);
+		assert_eq!(cats["Pip"].name, name);
+
+		let res = kadis.hkeys("cats").await;
+		assert_eq!(res.unwrap(), vec!["Pip".to_string(), "herb".into()]);
+	});
+}
+
+#[test]
+fn large() {
+	// Reads skip the cache so they reassemble the chunks every time
+	let mut kadis = KadisBuilder::local().read_through(false).init().unwrap();
+
+	// Random bytes do not compress, so this is split into hundreds of chunks
+	let mut rng = StdRng::seed_from_u64(7);
+	let mut value = vec![0u8; 4 * 1024 * 1024];
+	rng.fill(&mut value[..]);
+
+	task::block_on(async move {
+		let res = kadis.hset_bytes("files", "a", &value).await;
+		assert!(res.is_ok());
+		let stored = kadis.node.stored_records();
+		assert!(stored > 500);
+
+		let res = kadis.hget_bytes("files", "a").await;
+		assert_eq!(res.unwrap(), value);
+
+		// Overwriting drops the chunks of the old value
+		value.reverse();
+		let res = kadis.hset_bytes("files", "a", &value).await;
+		assert!(res.is_ok());
+		assert_eq!(kadis.node.stored_records(), stored);
+
+		let res = kadis.hget_bytes("files", "a").await;
+		assert_eq!(res.unwrap(), value);
+
+		let res = kadis.hdel("files", "a").await;
+		assert!(res.is_ok());
+		assert!(kadis.node.stored_records() < 5);
+	});
+}
+
+#[test]
+fn scan_keys() {
+	let mut kadis = KadisBuilder::local().init().unwrap();
+
+	task::block_on(async move {
+		for key in &["cats", "cars", "dogs"] {
+			let res = kadis.hset(key, "herb", 1u32).await;
+			assert!(res.is_ok());
+		}
+		let res = kadis.rpush("birds", 1u32).await;
+		assert!(res.is_ok());
+		kadis.node.put_legacy("kh-fields-fish", b"nemo");
+
+		let keys: Vec<String> = kadis.scan(Scan::new().count(2)).map(Result::unwrap).collect().await;
+		assert_eq!(keys, vec!["birds", "cars", "cats", "dogs", "fish"]);
+
+		let (next, page) = kadis.scan_page(&Scan::new().count(3).pattern("ca*")).await.unwrap();
+		assert_eq!(next, 3);
+		assert_eq!(page, vec!["cars", "cats"]);
+
+		// Keys of other namespaces are not seen
+		let res = kadis.with_namespace("dev").scan_page(&Scan::new()).await;
+		assert_eq!(res.unwrap(), (0, Vec::new()));
+	});
+}
@@ -0,0 +1,218 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::fmt::{self, Write};
+
+/// Key of a record holding part of a data type. Every part of the key is
+/// length-prefixed, so user keys and fields may contain any character.
+pub struct RecordKey {
+	name: String,
+	key: String,
+	legacy: Option<String>,
+	value: bool,
+}
+
+impl RecordKey {
+	fn new(kind: &str, parts: &[&str], legacy: Option<String>, value: bool) -> Self {
+		let mut key = format!("k2{}", kind);
+		for part in parts {
+			let _ = write!(key, ":{}:{}", part.len(), part);
+		}
+		Self {
+			name: parts[0].into(),
+			key,
+			legacy,
+			value,
+		}
+	}
+
+	/// The user key the record belongs to.
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn as_str(&self) -> &str {
+		&self.key
+	}
+
+	/// Where the record was kept before keys were length-prefixed.
+	pub fn legacy(&self) -> Option<&str> {
+		self.legacy.as_deref()
+	}
+
+	/// Whether the record holds a user value rather than an index.
+	pub fn holds_value(&self) -> bool {
+		self.value
+	}
+}
+
+impl fmt::Display for RecordKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.key)
+	}
+}
+
+pub fn hash_field(key: &str, field: &str) -> RecordKey {
+	RecordKey::new("h", &[key, field], Some(format!("kh-{}-{}", key, field)), true)
+}
+
+pub fn hash_fields(key: &str) -> RecordKey {
+	RecordKey::new("hf", &[key], Some(format!("kh-fields-{}", key)), false)
+}
+
+pub fn list_items(key: &str) -> RecordKey {
+	RecordKey::new("li", &[key], Some(format!("kl-items-{}", key)), false)
+}
+
+pub fn list_item(id: &str) -> RecordKey {
+	RecordKey::new("le", &[id], Some(format!("kl-{}", id)), true)
+}
+
+pub fn list_segment(key: &str, segment: u64) -> RecordKey {
+	RecordKey::new("ls", &[key, &segment.to_string()], None, false)
+}
+
+// Takes `{len}:{part}` off the front of `key`
+fn take_part(key: &str) -> Option<(&str, &str)> {
+	let (len, rest) = key.split_at(key.find(':')?);
+	let len: usize = len.parse().ok()?;
+	let rest = &rest[1..];
+	if rest.len() < len || !rest.is_char_boundary(len) {
+		return None;
+	}
+	Some(rest.split_at(len))
+}
+
+// Removes the `n{len}:{namespace}:{generation}:` wrapper of namespaced keys
+fn strip_namespace(key: &str) -> &str {
+	let unwrapped = key.strip_prefix('n')
+		.and_then(take_part)
+		.and_then(|(_, rest)| rest.strip_prefix(':'))
+		.and_then(|rest| rest.find(':').map(|end| &rest[end + 1..]));
+	unwrapped.unwrap_or(key)
+}
+
+/// The user key whose hash fields or list items are indexed under
+/// `record_key`, if it is such an index. Every hash and list has one.
+pub fn index_owner(record_key: &str) -> Option<&str> {
+	if let Some(rest) = record_key.strip_prefix("k2") {
+		let (kind, parts) = rest.split_at(rest.find(':')?);
+		return match kind {
+			"hf" | "li" => parts.strip_prefix(':').and_then(take_part).map(|(owner, _)| owner),
+			_ => None,
+		};
+	}
+	record_key.strip_prefix("kh-fields-").or_else(|| record_key.strip_prefix("kl-items-"))
+}
+
+/// Whether `record_key` is a key records were kept under before keys were
+/// length-prefixed. Those records hold the bare value.
+pub fn is_legacy(record_key: &str) -> bool {
+	let key = strip_namespace(record_key);
+	key.starts_with("kh-") || key.starts_with("kl-")
+}
+
+/// The user key a record stored under `record_key` belongs to, taken from the
+/// record key wherever it names one so that signers cannot pick it. List items
+/// are named by id alone, so they belong to whichever list `claimed` says.
+pub fn owner<'a>(record_key: &'a str, claimed: &'a str) -> &'a str {
+	let key = strip_namespace(record_key);
+
+	if let Some(rest) = key.strip_prefix("k2") {
+		let (kind, parts) = rest.split_at(rest.find(':').unwrap_or(rest.len()));
+		if kind == "le" {
+			return claimed;
+		}
+		return match parts.strip_prefix(':').and_then(take_part) {
+			Some((owner, _)) => owner,
+			None => key,
+		};
+	}
+
+	// Legacy hash keys are dash-joined, so only a claimed owner they start with is trusted
+	if let Some(rest) = key.strip_prefix("kh-") {
+		let claimed_field = matches!(rest.strip_prefix(claimed), Some(field) if field.starts_with('-'));
+		return match claimed_field || rest.strip_prefix("fields-") == Some(claimed) {
+			true => claimed,
+			false => rest,
+		};
+	}
+	if let Some(rest) = key.strip_prefix("kl-") {
+		return rest.strip_prefix("items-").unwrap_or(claimed);
+	}
+	// Generation records belong to the namespace they flush
+	if let Some(namespace) = key.strip_prefix("kg-") {
+		return namespace;
+	}
+
+	key
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{hash_field, hash_fields, list_item, list_items, list_segment, index_owner, is_legacy, owner};
+
+	#[test]
+	fn unambiguous() {
+		assert_ne!(hash_field("a-b", "c").as_str(), hash_field("a", "b-c").as_str());
+		assert_eq!(hash_field("a-b", "c").legacy(), hash_field("a", "b-c").legacy());
+		assert_ne!(hash_fields("x").as_str(), hash_field("fields", "x").as_str());
+		assert_ne!(list_items("x").as_str(), list_item("items-x").as_str());
+
+		let key = hash_field("cats", "name");
+		assert_eq!(key.as_str(), "k2h:4:cats:4:name");
+		assert_eq!(key.legacy(), Some("kh-cats-name"));
+		assert_eq!(list_segment("cats", 7).legacy(), None);
+		assert_eq!(key.name(), "cats");
+	}
+
+	#[test]
+	fn owners() {
+		assert_eq!(owner(hash_field("a:b", "c").as_str(), "mine"), "a:b");
+		assert_eq!(owner(hash_fields("cats").as_str(), ""), "cats");
+		assert_eq!(owner(list_segment("cats", 3).as_str(), "dogs"), "cats");
+		assert_eq!(owner(list_item("1234").as_str(), "cats"), "cats");
+		assert_eq!(owner(&format!("n3:dev:2:{}", list_items("cats")), "mine"), "cats");
+
+		assert_eq!(owner("kh-team-a:cats-name", "team-a:cats"), "team-a:cats");
+		assert_eq!(owner("kh-team-a:cats-name", "mine"), "team-a:cats-name");
+		assert_eq!(owner("kh-fields-cats", "cats"), "cats");
+		assert_eq!(owner("kl-items-cats", "mine"), "cats");
+		assert_eq!(owner("kg-dev", "mine"), "dev");
+	}
+
+	#[test]
+	fn legacy_keys() {
+		assert!(is_legacy("kh-cats-name"));
+		assert!(is_legacy("n3:dev:0:kl-items-cats"));
+		assert!(!is_legacy(hash_field("kh-cats", "name").as_str()));
+		assert!(!is_legacy("kg-dev"));
+		assert!(hash_field("cats", "name").holds_value());
+		assert!(!list_items("cats").holds_value());
+	}
+
+	#[test]
+	fn index_owners() {
+		assert_eq!(index_owner(hash_fields("a:b").as_str()), Some("a:b"));
+		assert_eq!(index_owner(list_items("cats").as_str()), Some("cats"));
+		assert_eq!(index_owner(hash_field("cats", "name").as_str()), None);
+		assert_eq!(index_owner(list_segment("cats", 0).as_str()), None);
+		assert_eq!(index_owner("kh-fields-cats"), Some("cats"));
+		assert_eq!(index_owner("kl-items-dogs"), Some("dogs"));
+		assert_eq!(index_owner("kh-cats-name"), None);
+		assert_eq!(index_owner("kg-dev"), None);
+	}
+}
@@ -16,18 +16,75 @@
 
 #![forbid(unsafe_code)]
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::Duration,
+};
 
+use futures::stream::{self, Stream, StreamExt};
 use serde::{de::DeserializeOwned, Serialize};
+use tracing::Instrument;
 
+mod acl;
+mod cache;
+mod chunk;
+mod codec;
+mod compression;
+mod crypto;
+mod error;
+mod fields;
 mod node;
+mod pipeline;
+mod runtime;
+mod scan;
+mod handle;
 mod hash;
+mod keys;
 mod list;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod signature;
+mod store;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod util;
 
-use node::{Node, NodeInitError};
+use cache::CacheConfig;
+use compression::CompressionConfig;
+use crypto::Keyring;
+use signature::{Validator, Writers};
+use node::{GetError, Node, NodeConfig, PutError};
+
+pub use acl::{Acl, AclError};
+pub use cache::CacheStats;
+pub use libp2p::{identity::Keypair, PeerId};
+pub use compression::Compression;
+pub use codec::{Bincode, Cbor, Codec, CodecError, Json, MessagePack, Visit};
+pub use error::{ErrorCode, KadisError};
+pub use fields::HashFields;
+pub use handle::{KadisHash, KadisList};
+#[cfg(feature = "derive")]
+pub use kadis_derive::KadisHash;
+pub use node::{FlushError, NodeInitError, ScanError};
+pub use pipeline::{Pipeline, Reply, Value};
+pub use runtime::{DefaultRuntime, Runtime};
+#[cfg(feature = "async-std")]
+pub use runtime::AsyncStd;
+#[cfg(feature = "tokio")]
+pub use runtime::Tokio;
+pub use scan::Scan;
+pub use signature::SignaturePolicy;
 use hash::*;
 use list::*;
+pub use hash::{
+    HDelError, HExistsError, HGetError, HGetAllError, HIncrError,
+    HKeysError, HLenError, HScanError, HSetError, HValsError,
+};
+pub use list::{
+    LCollectError, LIndexError, LInsertError, LLenError, LPopError, LPosError,
+    LPushError, LRangeError, LRemError, LScanError, LSetError, LTrimError,
+};
 
 pub enum Cmd<'a> {
 	Hash(HashCmd<'a>),
@@ -39,17 +96,73 @@ pub enum CmdResult {
     List(ListResult),
 }
 
+impl Cmd<'_> {
+    pub fn key(&self) -> &str {
+        match self {
+            Cmd::Hash(cmd) => cmd.key(),
+            Cmd::List(cmd) => cmd.key(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cmd::Hash(cmd) => cmd.name(),
+            Cmd::List(cmd) => cmd.name(),
+        }
+    }
+}
+
+impl CmdResult {
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            CmdResult::Hash(res) => res.code(),
+            CmdResult::List(res) => res.code(),
+        }
+    }
+}
+
 async fn handle_cmd(node: &mut Node, cmd: Cmd<'_>) -> CmdResult {
-	match cmd {
-        Cmd::Hash(cmd) => CmdResult::Hash(handle_hash_cmd(node, cmd).await),
-        Cmd::List(cmd) => CmdResult::List(handle_list_cmd(node, cmd).await),
-	}
+    let span = tracing::info_span!("cmd", command = cmd.name(), key = cmd.key(), outcome = tracing::field::Empty);
+    node.scope(cmd.key());
+    #[cfg(feature = "metrics")]
+    let (name, started) = (cmd.name(), std::time::Instant::now());
+	let res = async {
+        match cmd {
+            Cmd::Hash(cmd) => CmdResult::Hash(handle_hash_cmd(node, cmd).await),
+            Cmd::List(cmd) => CmdResult::List(handle_list_cmd(node, cmd).await),
+        }
+	}.instrument(span.clone()).await;
+    span.record("outcome", res.code().map_or("ok", |code| code.as_str()));
+    #[cfg(feature = "metrics")]
+    node.metrics().command(name, started.elapsed(), res.code());
+    res
 }
 
+#[derive(Clone)]
 pub struct KadisBuilder<'a> {
     bootstraps: &'a [&'a str],
     port: u16,
     cache_lifetime: u64,
+    cache_capacity: usize,
+    read_through: bool,
+    codec: Arc<dyn Codec>,
+    compression: Compression,
+    compression_threshold: usize,
+    keyring: Keyring,
+    identity: Option<Keypair>,
+    signature_policy: SignaturePolicy,
+    writers: Writers,
+    acl_admins: Vec<PeerId>,
+    namespace: &'a str,
+    legacy_keys: bool,
+    parallelism: usize,
+    local: bool,
+    memory: bool,
+    query_timeout: Duration,
+    #[cfg(feature = "metrics")]
+    metrics_address: Option<&'a str>,
+    #[cfg(any(test, feature = "testing"))]
+    faults: Option<testing::Faults>,
 }
 
 impl<'a> KadisBuilder<'a> {
@@ -58,6 +171,7 @@ impl<'a> KadisBuilder<'a> {
             bootstraps,
             port,
             cache_lifetime,
+            ..Self::default()
         }
     }
 
@@ -66,48 +180,390 @@ impl<'a> KadisBuilder<'a> {
             bootstraps: &[],
             port: 0,
             cache_lifetime: 60,
+            cache_capacity: 1024,
+            read_through: true,
+            codec: Arc::new(Bincode),
+            compression: Compression::None,
+            compression_threshold: 1024,
+            keyring: Keyring::default(),
+            identity: None,
+            signature_policy: SignaturePolicy::Verify,
+            writers: Writers::default(),
+            acl_admins: Vec::new(),
+            namespace: "",
+            legacy_keys: true,
+            parallelism: 16,
+            local: false,
+            memory: false,
+            query_timeout: Duration::from_secs(60),
+            #[cfg(feature = "metrics")]
+            metrics_address: None,
+            #[cfg(any(test, feature = "testing"))]
+            faults: None,
+        }
+    }
+
+    /// Builder for a node that keeps everything in memory in this process,
+    /// without listening, discovering or dialing any peers.
+    pub fn local() -> Self {
+        Self {
+            local: true,
+            ..Self::default()
         }
     }
 
     pub fn bootstraps(&self, bootstraps: &'a [&'a str]) -> Self {
         Self {
             bootstraps,
-            port: self.port,
-            cache_lifetime: self.cache_lifetime,
+            ..self.clone()
         }
     }
 
     pub fn port(&self, port: u16) -> Self {
         Self {
-            bootstraps: self.bootstraps,
             port,
-            cache_lifetime: self.cache_lifetime,
+            ..self.clone()
         }
     }
 
     pub fn cache_lifetime(&self, cache_lifetime: u64) -> Self {
         Self {
-            bootstraps: self.bootstraps,
-            port: self.port,
             cache_lifetime,
+            ..self.clone()
+        }
+    }
+
+    pub fn cache_capacity(&self, cache_capacity: usize) -> Self {
+        Self {
+            cache_capacity,
+            ..self.clone()
+        }
+    }
+
+    pub fn read_through(&self, read_through: bool) -> Self {
+        Self {
+            read_through,
+            ..self.clone()
+        }
+    }
+
+    pub fn codec<C>(&self, codec: C) -> Self
+    where C: Codec + 'static {
+        Self {
+            codec: Arc::new(codec),
+            ..self.clone()
+        }
+    }
+
+    pub fn compression(&self, compression: Compression) -> Self {
+        Self {
+            compression,
+            ..self.clone()
+        }
+    }
+
+    pub fn compression_threshold(&self, compression_threshold: usize) -> Self {
+        Self {
+            compression_threshold,
+            ..self.clone()
+        }
+    }
+
+    /// Encrypts every key starting with `prefix` using XChaCha20-Poly1305.
+    /// Adding another key for the same prefix rotates to it: new writes use the
+    /// latest `key_id`, while values sealed under older ids stay readable.
+    pub fn encryption_key(&self, prefix: &str, key_id: u32, key: [u8; 32]) -> Self {
+        let mut keyring = self.keyring.clone();
+        keyring.insert(prefix, key_id, key);
+        Self {
+            keyring,
+            ..self.clone()
+        }
+    }
+
+    /// Identity records are signed with. A new ed25519 keypair is generated
+    /// when none is given, so set one if other nodes list this one as a writer.
+    pub fn identity(&self, identity: Keypair) -> Self {
+        Self {
+            identity: Some(identity),
+            ..self.clone()
+        }
+    }
+
+    pub fn signature_policy(&self, signature_policy: SignaturePolicy) -> Self {
+        Self {
+            signature_policy,
+            ..self.clone()
+        }
+    }
+
+    /// Only lets `peers` write keys starting with `prefix`. Records for these
+    /// keys signed by anyone else are refused when stored and when read.
+    pub fn writers(&self, prefix: &str, peers: &[PeerId]) -> Self {
+        let mut writers = self.writers.clone();
+        writers.insert(prefix, peers);
+        Self {
+            writers,
+            ..self.clone()
+        }
+    }
+
+    /// Peers trusted to set ACLs that no other ACL governs, such as the ACL
+    /// for `team-a:`. Every node must list the same peers, since each checks
+    /// ACL records against its own list. Without any, only ACLs below an
+    /// existing one can be set.
+    pub fn acl_admins(&self, acl_admins: &[PeerId]) -> Self {
+        Self {
+            acl_admins: acl_admins.to_vec(),
+            ..self.clone()
+        }
+    }
+
+    /// Namespace keys are kept in. Nodes sharing a network only see each
+    /// other's keys when they use the same namespace.
+    pub fn namespace(&self, namespace: &'a str) -> Self {
+        Self {
+            namespace,
+            ..self.clone()
+        }
+    }
+
+    /// Whether records missing under their current key are looked up under the
+    /// dash-joined keys used before. Reads of a missing record then take two
+    /// lookups, so turn this off once old data has been rewritten or expired.
+    pub fn legacy_keys(&self, legacy_keys: bool) -> Self {
+        Self {
+            legacy_keys,
+            ..self.clone()
+        }
+    }
+
+    /// Most records fetched at once by commands reading a whole collection,
+    /// such as `hgetall` and `lrange`.
+    pub fn parallelism(&self, parallelism: usize) -> Self {
+        Self {
+            parallelism,
+            ..self.clone()
+        }
+    }
+
+    /// Connects to peers over libp2p's in-process memory transport instead of
+    /// TCP and mDNS. The port and bootstraps are then `/memory/` addresses.
+    pub fn memory(&self, memory: bool) -> Self {
+        Self {
+            memory,
+            ..self.clone()
+        }
+    }
+
+    /// How long a DHT query may take before failing with a timeout.
+    pub fn query_timeout(&self, query_timeout: Duration) -> Self {
+        Self {
+            query_timeout,
+            ..self.clone()
+        }
+    }
+
+    /// Serves the node's metrics in the Prometheus text format over HTTP at
+    /// `/metrics` on `address`, such as `127.0.0.1:9100`.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_address(&self, metrics_address: &'a str) -> Self {
+        Self {
+            metrics_address: Some(metrics_address),
+            ..self.clone()
+        }
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) fn faults(&self, faults: testing::Faults) -> Self {
+        Self {
+            faults: Some(faults),
+            ..self.clone()
         }
     }
 
     pub fn init(&self) -> Result<Kadis, NodeInitError> {
-        let node = Node::new(self.bootstraps, self.port, self.cache_lifetime)?;
+        let cache_config = CacheConfig {
+            capacity: self.cache_capacity,
+            lifetime: Duration::from_secs(self.cache_lifetime),
+            read_through: self.read_through,
+        };
+        let compression_config = CompressionConfig {
+            compression: self.compression,
+            threshold: self.compression_threshold,
+        };
+        let node = Node::new(NodeConfig {
+            bootstraps: self.bootstraps,
+            port: self.port,
+            cache: cache_config,
+            compression: compression_config,
+            keyring: self.keyring.clone(),
+            identity: self.identity.clone().unwrap_or_else(Keypair::generate_ed25519),
+            validator: Validator {
+                policy: self.signature_policy,
+                writers: self.writers.clone(),
+            },
+            acl_admins: self.acl_admins.clone(),
+            namespace: self.namespace,
+            legacy_keys: self.legacy_keys,
+            parallelism: self.parallelism,
+            local: self.local,
+            memory: self.memory,
+            query_timeout: self.query_timeout,
+            #[cfg(feature = "metrics")]
+            metrics_address: self.metrics_address,
+            #[cfg(any(test, feature = "testing"))]
+            faults: self.faults.clone(),
+        })?;
         drop(self);
 
         Ok(Kadis {
             node,
+            codec: self.codec.clone(),
         })
     }
 }
 
+#[derive(Clone)]
 pub struct Kadis {
     node: Node,
+    codec: Arc<dyn Codec>,
 }
 
 impl Kadis {
+    pub fn cache_stats(&self) -> CacheStats {
+        self.node.cache_stats()
+    }
+
+    /// Command, query, cache and peer statistics in the Prometheus text format.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> String {
+        self.node.render_metrics()
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.node.peer_id()
+    }
+
+    /// Returns a handle sharing this node that works in `namespace`.
+    pub fn with_namespace(&self, namespace: &str) -> Kadis {
+        Kadis {
+            node: self.node.with_namespace(namespace),
+            codec: self.codec.clone(),
+        }
+    }
+
+    /// Wipes every key in this handle's namespace.
+    pub async fn flush(&mut self) -> Result<(), FlushError> {
+        let namespace = self.node.namespace().to_string();
+        match self.node.flush().await {
+            Ok(()) => Ok(()),
+            Err(err) => Err(match err {
+                PutError::QuorumFailed => FlushError::QuorumFailed { namespace },
+                PutError::Timeout => FlushError::Timeout { namespace },
+                PutError::Rejected => FlushError::Rejected { namespace },
+            }),
+        }
+    }
+
+    /// Reads one page of the keys of hashes and lists this node stores, returning
+    /// it with the cursor of the next page, which is 0 once every key has been
+    /// read. Keys only stored by other nodes are not seen.
+    pub async fn scan_page(&mut self, scan: &Scan<'_>) -> Result<(usize, Vec<String>), ScanError> {
+        let namespace = self.node.namespace().to_string();
+        let keys = self.node.local_keys().await.map_err(|err| match err {
+            GetError::QuorumFailed => ScanError::QuorumFailed { namespace },
+            GetError::Timeout => ScanError::Timeout { namespace },
+            GetError::NotFound | GetError::Invalid => ScanError::Invalid { namespace },
+        })?;
+        let len = keys.len();
+
+        let start = scan.cursor.min(len);
+        let end = start.saturating_add(scan.count.max(1)).min(len);
+        let page = keys[start..end].iter()
+            .filter(|key| match scan.pattern {
+                Some(pattern) => scan::glob_match(pattern, key),
+                None => true,
+            })
+            .cloned()
+            .collect();
+
+        // Like Redis, a cursor of 0 means every key has been read
+        let next = if end < len { end } else { 0 };
+        Ok((next, page))
+    }
+
+    /// Streams the keys of hashes and lists this node stores, holding one page
+    /// at a time.
+    pub fn scan<'a>(&self, scan: Scan<'a>) -> impl Stream<Item = Result<String, ScanError>> + 'a {
+        stream::unfold((self.clone(), Some(scan)), move |(mut kadis, scan)| async move {
+            let scan = scan?;
+            let (next, page) = match kadis.scan_page(&scan).await {
+                Ok((0, page)) => (None, page.into_iter().map(Ok).collect()),
+                Ok((cursor, page)) => (Some(scan.cursor(cursor)), page.into_iter().map(Ok).collect()),
+                Err(err) => (None, vec![Err(err)]),
+            };
+            Some((stream::iter(page), (kadis, next)))
+        })
+        .flatten()
+    }
+
+    /// Fetches the ACL for `prefix`. Once fetched it is enforced by this node.
+    pub async fn acl(&mut self, prefix: &str) -> Result<Acl, AclError> {
+        match self.node.get_acl(prefix).await {
+            Ok(acl) => Ok(acl),
+            Err(err) => Err(match err {
+                GetError::NotFound => AclError::NotFound { prefix: prefix.into() },
+                GetError::QuorumFailed => AclError::QuorumFailed { prefix: prefix.into() },
+                GetError::Timeout => AclError::Timeout { prefix: prefix.into() },
+                GetError::Invalid => AclError::Invalid { prefix: prefix.into() },
+            }),
+        }
+    }
+
+    /// Sets who may read, write and delete keys starting with `prefix`. Writes
+    /// are refused by storage nodes that know the ACL, while reads and deletes
+    /// can only be checked by clients, so encrypt values that must stay private.
+    pub async fn set_acl(&mut self, prefix: &str, acl: &Acl) -> Result<(), AclError> {
+        if !self.node.may_update_acl(prefix) {
+            return Err(AclError::PermissionDenied { prefix: prefix.into() });
+        }
+        match self.node.put_acl(prefix, acl).await {
+            Ok(()) => Ok(()),
+            Err(err) => Err(match err {
+                PutError::QuorumFailed => AclError::QuorumFailed { prefix: prefix.into() },
+                PutError::Timeout => AclError::Timeout { prefix: prefix.into() },
+                PutError::Rejected => AclError::Rejected { prefix: prefix.into() },
+            }),
+        }
+    }
+
+    /// Returns a handle to the hash at `key`, whose values are all of type `T`.
+    pub fn hash<T>(&self, key: &str) -> KadisHash<T>
+    where T: Serialize + DeserializeOwned {
+        KadisHash::new(self.clone(), key)
+    }
+
+    /// Returns a handle to the list at `key`, whose items are all of type `T`.
+    pub fn list<T>(&self, key: &str) -> KadisList<T>
+    where T: Serialize + DeserializeOwned {
+        KadisList::new(self.clone(), key)
+    }
+
+    /// Starts a pipeline of commands whose queries are all sent together.
+    pub fn pipeline<'a>(&self) -> Pipeline<'a> {
+        Pipeline::new(self.node.clone(), self.codec.clone())
+    }
+
+    /// Returns a handle sharing this node that reads and writes values with `codec`.
+    pub fn with_codec<C>(&self, codec: C) -> Kadis
+    where C: Codec + 'static {
+        Kadis {
+            node: self.node.clone(),
+            codec: Arc::new(codec),
+        }
+    }
+
 	pub async fn hdel(&mut self, key: &str, field: &str) -> Result<(), HDelError> {
 		let fields = &[field];
         let cmd = Cmd::Hash(HashCmd::Del(key, fields));
@@ -133,50 +589,120 @@ impl Kadis {
         }
     }
 
-    pub async fn hget<T>(&mut self, key: &str, field: &str) -> Result<T, HGetError>
-    where T: DeserializeOwned {
+    async fn hget_raw(&mut self, key: &str, field: &str) -> Result<Vec<u8>, HGetError> {
         let cmd = Cmd::Hash(HashCmd::Get(key, field));
         match handle_cmd(&mut self.node, cmd).await {
-            CmdResult::Hash(HashResult::Get(res)) => match res {
-                Ok(data) => Ok(bincode::deserialize(&data).unwrap()),
-                Err(err) => Err(err),
-            },
+            CmdResult::Hash(HashResult::Get(res)) => res,
             _ => unreachable!(),
         }
     }
 
-    pub async fn hget_multiple<T>(&mut self, key: &str, fields: &[&str]) -> Result<Vec<T>, HGetError>
+    pub async fn hget<T>(&mut self, key: &str, field: &str) -> Result<T, HGetError>
     where T: DeserializeOwned {
+        let data = self.hget_raw(key, field).await?;
+        self.codec.decode(&data).map_err(|cause| HGetError::Decode {
+            key: key.into(),
+            field: field.into(),
+            cause: cause.into(),
+        })
+    }
+
+    pub async fn hget_bytes(&mut self, key: &str, field: &str) -> Result<Vec<u8>, HGetError> {
+        let data = self.hget_raw(key, field).await?;
+        codec::payload(data).map_err(|cause| HGetError::Decode {
+            key: key.into(),
+            field: field.into(),
+            cause: cause.into(),
+        })
+    }
+
+    async fn hget_multiple_raw(&mut self, key: &str, fields: &[&str]) -> Result<Vec<Vec<u8>>, HGetError> {
         let cmd = Cmd::Hash(HashCmd::GetM(key, fields));
         match handle_cmd(&mut self.node, cmd).await {
-            CmdResult::Hash(HashResult::GetM(res)) => match res {
-                Ok(data) => Ok(data.iter()
-                    .map(|d| bincode::deserialize(d).unwrap())
-                    .collect()),
-                Err(err) => Err(err),
-            },
+            CmdResult::Hash(HashResult::GetM(res)) => res,
             _ => unreachable!(),
         }
     }
 
-    pub async fn hgetall<T>(&mut self, key: &str) -> Result<HashMap<String, T>, HGetAllError>
+    pub async fn hget_multiple<T>(&mut self, key: &str, fields: &[&str]) -> Result<Vec<T>, HGetError>
     where T: DeserializeOwned {
+        let data = self.hget_multiple_raw(key, fields).await?;
+        data.iter()
+            .zip(fields)
+            .map(|(d, field)| self.codec.decode(d).map_err(|cause| HGetError::Decode {
+                key: key.into(),
+                field: (*field).into(),
+                cause: cause.into(),
+            }))
+            .collect()
+    }
+
+    pub async fn hget_multiple_bytes(&mut self, key: &str, fields: &[&str]) -> Result<Vec<Vec<u8>>, HGetError> {
+        let data = self.hget_multiple_raw(key, fields).await?;
+        data.into_iter()
+            .zip(fields)
+            .map(|(d, field)| codec::payload(d).map_err(|cause| HGetError::Decode {
+                key: key.into(),
+                field: (*field).into(),
+                cause: cause.into(),
+            }))
+            .collect()
+    }
+
+    /// Loads a struct stored with `hset_struct` from the hash at `key`.
+    pub async fn hget_struct<T>(&mut self, key: &str) -> Result<T, HGetError>
+    where T: HashFields {
+        let values = self.hget_multiple_raw(key, T::fields()).await?;
+        T::decode_fields(&values, &*self.codec).map_err(|(field, cause)| HGetError::Decode {
+            key: key.into(),
+            field: field.into(),
+            cause: cause.into(),
+        })
+    }
+
+    async fn hgetall_raw(&mut self, key: &str) -> Result<HashMap<String, Vec<u8>>, HGetAllError> {
         let cmd = Cmd::Hash(HashCmd::GetAll(key));
         match handle_cmd(&mut self.node, cmd).await {
-            CmdResult::Hash(HashResult::GetAll(res)) => match res {
-                Ok(map) => {
-                    let mut data = HashMap::new();
+            CmdResult::Hash(HashResult::GetAll(res)) => res,
+            _ => unreachable!(),
+        }
+    }
 
-                    for (field, d) in map {
-                        data.insert(field, bincode::deserialize(&d).unwrap());
-                    }
+    pub async fn hgetall<T>(&mut self, key: &str) -> Result<HashMap<String, T>, HGetAllError>
+    where T: DeserializeOwned {
+        let map = self.hgetall_raw(key).await?;
+        let mut data = HashMap::new();
 
-                    Ok(data)
-                },
-                Err(err) => Err(err),
-            },
-            _ => unreachable!(),
+        for (field, d) in map {
+            match self.codec.decode(&d) {
+                Ok(value) => data.insert(field, value),
+                Err(cause) => return Err(HGetAllError::Decode {
+                    key: key.into(),
+                    field,
+                    cause: cause.into(),
+                }),
+            };
         }
+
+        Ok(data)
+    }
+
+    pub async fn hgetall_bytes(&mut self, key: &str) -> Result<HashMap<String, Vec<u8>>, HGetAllError> {
+        let map = self.hgetall_raw(key).await?;
+        let mut data = HashMap::new();
+
+        for (field, d) in map {
+            match codec::payload(d) {
+                Ok(value) => data.insert(field, value),
+                Err(cause) => return Err(HGetAllError::Decode {
+                    key: key.into(),
+                    field,
+                    cause: cause.into(),
+                }),
+            };
+        }
+
+        Ok(data)
     }
 
     pub async fn hincr(&mut self, key: &str, field: &str, inc: u32) -> Result<(), HIncrError> {
@@ -207,9 +733,65 @@ impl Kadis {
         }
     }
 
-    pub async fn hset<T>(&mut self, key: &str, field: &str, value: T) -> Result<(), HSetError>
-    where T: Serialize {
-        let value = bincode::serialize(&value).unwrap();
+    async fn hscan_raw(&mut self, key: &str, scan: &Scan<'_>) -> Result<HashPage, HScanError> {
+        let cmd = Cmd::Hash(HashCmd::Scan(key, scan.cursor, scan.count, scan.pattern));
+        match handle_cmd(&mut self.node, cmd).await {
+            CmdResult::Hash(HashResult::Scan(res)) => res,
+            _ => unreachable!(),
+        }
+    }
+
+    async fn hscan_decoded<T>(&mut self, key: &str, scan: &Scan<'_>, decode: fn(&Kadis, Vec<u8>) -> Result<T, CodecError>) -> Result<(usize, Vec<(String, T)>), HScanError> {
+        let (cursor, page) = self.hscan_raw(key, scan).await?;
+        let page = page.into_iter()
+            .map(|(field, d)| match decode(self, d) {
+                Ok(value) => Ok((field, value)),
+                Err(cause) => Err(HScanError::Decode {
+                    key: key.into(),
+                    field,
+                    cause: cause.into(),
+                }),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((cursor, page))
+    }
+
+    /// Reads one page of the hash at `key`, returning it with the cursor of
+    /// the next page, which is 0 once the whole hash has been read.
+    pub async fn hscan_page<T>(&mut self, key: &str, scan: &Scan<'_>) -> Result<(usize, Vec<(String, T)>), HScanError>
+    where T: DeserializeOwned {
+        self.hscan_decoded(key, scan, |kadis, d| kadis.codec.decode(&d)).await
+    }
+
+    pub async fn hscan_page_bytes(&mut self, key: &str, scan: &Scan<'_>) -> Result<(usize, Vec<(String, Vec<u8>)>), HScanError> {
+        self.hscan_decoded(key, scan, |_, d| codec::payload(d)).await
+    }
+
+    fn hscan_stream<'a, T>(&self, key: &'a str, scan: Scan<'a>, decode: fn(&Kadis, Vec<u8>) -> Result<T, CodecError>) -> impl Stream<Item = Result<(String, T), HScanError>> + 'a
+    where T: 'a {
+        stream::unfold((self.clone(), Some(scan)), move |(mut kadis, scan)| async move {
+            let scan = scan?;
+            let (next, page) = match kadis.hscan_decoded(key, &scan, decode).await {
+                Ok((0, page)) => (None, page.into_iter().map(Ok).collect()),
+                Ok((cursor, page)) => (Some(scan.cursor(cursor)), page.into_iter().map(Ok).collect()),
+                Err(err) => (None, vec![Err(err)]),
+            };
+            Some((stream::iter(page), (kadis, next)))
+        })
+        .flatten()
+    }
+
+    /// Streams the fields of the hash at `key`, holding one page at a time.
+    pub fn hscan<'a, T>(&self, key: &'a str, scan: Scan<'a>) -> impl Stream<Item = Result<(String, T), HScanError>> + 'a
+    where T: DeserializeOwned + 'a {
+        self.hscan_stream(key, scan, |kadis, d| kadis.codec.decode(&d))
+    }
+
+    pub fn hscan_bytes<'a>(&self, key: &'a str, scan: Scan<'a>) -> impl Stream<Item = Result<(String, Vec<u8>), HScanError>> + 'a {
+        self.hscan_stream(key, scan, |_, d| codec::payload(d))
+    }
+
+    async fn hset_raw(&mut self, key: &str, field: &str, value: Vec<u8>) -> Result<(), HSetError> {
         let cmd = Cmd::Hash(HashCmd::Set(key, field, value));
         match handle_cmd(&mut self.node, cmd).await {
             CmdResult::Hash(HashResult::Set(res)) => res,
@@ -217,9 +799,21 @@ impl Kadis {
         }
     }
 
-    pub async fn hset_multiple<T>(&mut self, key: &str, fields: &[&str], values: &[T]) -> Result<(), HSetError>
+    pub async fn hset<T>(&mut self, key: &str, field: &str, value: T) -> Result<(), HSetError>
     where T: Serialize {
-        let values = values.iter().map(|v| bincode::serialize(&v).unwrap()).collect();
+        let value = self.codec.encode(&value).map_err(|cause| HSetError::Encode {
+            key: key.into(),
+            field: field.into(),
+            cause: cause.into(),
+        })?;
+        self.hset_raw(key, field, value).await
+    }
+
+    pub async fn hset_bytes(&mut self, key: &str, field: &str, value: &[u8]) -> Result<(), HSetError> {
+        self.hset_raw(key, field, codec::raw(value)).await
+    }
+
+    async fn hset_multiple_raw(&mut self, key: &str, fields: &[&str], values: Vec<Vec<u8>>) -> Result<(), HSetError> {
         let cmd = Cmd::Hash(HashCmd::SetM(key, fields, values));
         match handle_cmd(&mut self.node, cmd).await {
             CmdResult::Hash(HashResult::SetM(res)) => res,
@@ -227,9 +821,55 @@ impl Kadis {
         }
     }
 
-    pub async fn hset_nx<T>(&mut self, key: &str, field: &str, value: T) -> Result<(), HSetError>
+    pub async fn hset_multiple<T>(&mut self, key: &str, fields: &[&str], values: &[T]) -> Result<(), HSetError>
     where T: Serialize {
-        let value = bincode::serialize(&value).unwrap();
+        // Checked before encoding, which would stop at the shorter of the two
+        if fields.len() != values.len() {
+            return Err(HSetError::LengthMismatch {
+                key: key.into(),
+                fields: fields.len(),
+                values: values.len(),
+            });
+        }
+        let values = values.iter()
+            .zip(fields)
+            .map(|(v, field)| self.codec.encode(v).map_err(|cause| HSetError::Encode {
+                key: key.into(),
+                field: (*field).into(),
+                cause: cause.into(),
+            }))
+            .collect::<Result<_, _>>()?;
+        self.hset_multiple_raw(key, fields, values).await
+    }
+
+    pub async fn hset_multiple_bytes(&mut self, key: &str, fields: &[&str], values: &[&[u8]]) -> Result<(), HSetError> {
+        let values = values.iter().map(|v| codec::raw(v)).collect();
+        self.hset_multiple_raw(key, fields, values).await
+    }
+
+    /// Stores every field of `value` as a field of the hash at `key`.
+    pub async fn hset_struct<T>(&mut self, key: &str, value: &T) -> Result<(), HSetError>
+    where T: HashFields {
+        self.hset_struct_fields(key, value, T::fields()).await
+    }
+
+    /// Stores only `fields` of `value`, leaving the rest of the hash as it is.
+    /// Names that are not fields of `T` are ignored.
+    pub async fn hset_struct_fields<T>(&mut self, key: &str, value: &T, fields: &[&str]) -> Result<(), HSetError>
+    where T: HashFields {
+        let values = value.encode_fields(&*self.codec).map_err(|(field, cause)| HSetError::Encode {
+            key: key.into(),
+            field: field.into(),
+            cause: cause.into(),
+        })?;
+        let (fields, values): (Vec<&str>, Vec<Vec<u8>>) = T::fields().iter()
+            .zip(values)
+            .filter(|(field, _)| fields.contains(field))
+            .unzip();
+        self.hset_multiple_raw(key, &fields, values).await
+    }
+
+    async fn hset_nx_raw(&mut self, key: &str, field: &str, value: Vec<u8>) -> Result<(), HSetError> {
         let cmd = Cmd::Hash(HashCmd::SetNx(key, field, value));
         match handle_cmd(&mut self.node, cmd).await {
             CmdResult::Hash(HashResult::SetNx(res)) => res,
@@ -237,49 +877,112 @@ impl Kadis {
         }
     }
 
-    pub async fn hvals<T>(&mut self, key: &str) -> Result<Vec<T>, HValsError>
-    where T: DeserializeOwned {
+    pub async fn hset_nx<T>(&mut self, key: &str, field: &str, value: T) -> Result<(), HSetError>
+    where T: Serialize {
+        let value = self.codec.encode(&value).map_err(|cause| HSetError::Encode {
+            key: key.into(),
+            field: field.into(),
+            cause: cause.into(),
+        })?;
+        self.hset_nx_raw(key, field, value).await
+    }
+
+    pub async fn hset_nx_bytes(&mut self, key: &str, field: &str, value: &[u8]) -> Result<(), HSetError> {
+        self.hset_nx_raw(key, field, codec::raw(value)).await
+    }
+
+    async fn hvals_raw(&mut self, key: &str) -> Result<Vec<(String, Vec<u8>)>, HValsError> {
         let cmd = Cmd::Hash(HashCmd::Vals(key));
         match handle_cmd(&mut self.node, cmd).await {
-            CmdResult::Hash(HashResult::Vals(res)) => match res {
-                Ok(data) => Ok(data.iter()
-                    .map(|d| bincode::deserialize(d).unwrap())
-                    .collect()),
-                Err(err) => Err(err),
-            },
+            CmdResult::Hash(HashResult::Vals(res)) => res,
             _ => unreachable!(),
         }
     }
 
-    pub async fn lcollect<T>(&mut self, key: &str) -> Result<Vec<T>, LCollectError>
+    pub async fn hvals<T>(&mut self, key: &str) -> Result<Vec<T>, HValsError>
     where T: DeserializeOwned {
+        let data = self.hvals_raw(key).await?;
+        data.into_iter()
+            .map(|(field, d)| self.codec.decode(&d).map_err(|cause| HValsError::Decode {
+                key: key.into(),
+                field,
+                cause: cause.into(),
+            }))
+            .collect()
+    }
+
+    pub async fn hvals_bytes(&mut self, key: &str) -> Result<Vec<Vec<u8>>, HValsError> {
+        let data = self.hvals_raw(key).await?;
+        data.into_iter()
+            .map(|(field, d)| codec::payload(d).map_err(|cause| HValsError::Decode {
+                key: key.into(),
+                field,
+                cause: cause.into(),
+            }))
+            .collect()
+    }
+
+    async fn lcollect_raw(&mut self, key: &str) -> Result<Vec<Vec<u8>>, LCollectError> {
         let cmd = Cmd::List(ListCmd::Collect(key));
         match handle_cmd(&mut self.node, cmd).await {
-            CmdResult::List(ListResult::Collect(res)) => match res {
-                Ok(data) => Ok(data.iter()
-                    .map(|d| bincode::deserialize(d).unwrap())
-                    .collect()),
-                Err(err) => Err(err),
-            },
+            CmdResult::List(ListResult::Collect(res)) => res,
             _ => unreachable!(),
         }
     }
 
-    pub async fn lindex<T>(&mut self, key: &str, index: isize) -> Result<T, LIndexError>
+    pub async fn lcollect<T>(&mut self, key: &str) -> Result<Vec<T>, LCollectError>
     where T: DeserializeOwned {
+        let data = self.lcollect_raw(key).await?;
+        data.iter()
+            .enumerate()
+            .map(|(index, d)| self.codec.decode(d).map_err(|cause| LCollectError::Decode {
+                key: key.into(),
+                index,
+                cause: cause.into(),
+            }))
+            .collect()
+    }
+
+    pub async fn lcollect_bytes(&mut self, key: &str) -> Result<Vec<Vec<u8>>, LCollectError> {
+        let data = self.lcollect_raw(key).await?;
+        data.into_iter()
+            .enumerate()
+            .map(|(index, d)| codec::payload(d).map_err(|cause| LCollectError::Decode {
+                key: key.into(),
+                index,
+                cause: cause.into(),
+            }))
+            .collect()
+    }
+
+    async fn lindex_raw(&mut self, key: &str, index: isize) -> Result<Vec<u8>, LIndexError> {
         let cmd = Cmd::List(ListCmd::Index(key, index));
         match handle_cmd(&mut self.node, cmd).await {
-            CmdResult::List(ListResult::Index(res)) => match res {
-                Ok(data) => Ok(bincode::deserialize(&data).unwrap()),
-                Err(err) => Err(err),
-            },
+            CmdResult::List(ListResult::Index(res)) => res,
             _ => unreachable!(),
         }
     }
 
-    async fn linsert<T>(&mut self, key: &str, index: isize, item: T, after: bool) -> Result<(), LInsertError>
-    where T: Serialize {
-        let item = bincode::serialize(&item).unwrap();
+    pub async fn lindex<T>(&mut self, key: &str, index: isize) -> Result<T, LIndexError>
+    where T: DeserializeOwned {
+        let data = self.lindex_raw(key, index).await?;
+        self.codec.decode(&data).map_err(|cause| LIndexError::Decode {
+            key: key.into(),
+            index,
+            cause: cause.into(),
+        })
+    }
+
+    pub async fn lindex_bytes(&mut self, key: &str, index: isize) -> Result<Vec<u8>, LIndexError> {
+        let data = self.lindex_raw(key, index).await?;
+        codec::payload(data).map_err(|cause| LIndexError::Decode {
+            key: key.into(),
+            index,
+            cause: cause.into(),
+        })
+    }
+
+    async fn linsert_raw(&mut self, key: &str, index: isize, item: Vec<u8>, after: bool) -> Result<(), LInsertError> {
         let cmd = Cmd::List(ListCmd::Insert(key, index, item, after));
         match handle_cmd(&mut self.node, cmd).await {
             CmdResult::List(ListResult::Insert(res)) => res,
@@ -287,6 +990,16 @@ impl Kadis {
         }
     }
 
+    async fn linsert<T>(&mut self, key: &str, index: isize, item: T, after: bool) -> Result<(), LInsertError>
+    where T: Serialize {
+        let item = self.codec.encode(&item).map_err(|cause| LInsertError::Encode {
+            key: key.into(),
+            index,
+            cause: cause.into(),
+        })?;
+        self.linsert_raw(key, index, item, after).await
+    }
+
     pub async fn linsert_before<T>(&mut self, key: &str, index: isize, item: T) -> Result<(), LInsertError>
     where T: Serialize {
         self.linsert(key, index, item, false).await
@@ -297,6 +1010,14 @@ impl Kadis {
         self.linsert(key, index, item, true).await
     }
 
+    pub async fn linsert_before_bytes(&mut self, key: &str, index: isize, item: &[u8]) -> Result<(), LInsertError> {
+        self.linsert_raw(key, index, codec::raw(item), false).await
+    }
+
+    pub async fn linsert_after_bytes(&mut self, key: &str, index: isize, item: &[u8]) -> Result<(), LInsertError> {
+        self.linsert_raw(key, index, codec::raw(item), true).await
+    }
+
     pub async fn llen(&mut self, key: &str) -> Result<usize, LLenError>  {
         let cmd = Cmd::List(ListCmd::Len(key));
         match handle_cmd(&mut self.node, cmd).await {
@@ -305,18 +1026,31 @@ impl Kadis {
         }
     }
 
-    async fn lrpop<T>(&mut self, key: &str, right: bool) -> Result<T, LPopError>
-    where T: DeserializeOwned {
+    async fn lrpop_raw(&mut self, key: &str, right: bool) -> Result<Vec<u8>, LPopError> {
         let cmd = Cmd::List(ListCmd::Pop(key, right));
         match handle_cmd(&mut self.node, cmd).await {
-            CmdResult::List(ListResult::Pop(res)) => match res {
-                Ok(data) => Ok(bincode::deserialize(&data).unwrap()),
-                Err(err) => Err(err),
-            },
+            CmdResult::List(ListResult::Pop(res)) => res,
             _ => unreachable!(),
         }
     }
 
+    async fn lrpop<T>(&mut self, key: &str, right: bool) -> Result<T, LPopError>
+    where T: DeserializeOwned {
+        let data = self.lrpop_raw(key, right).await?;
+        self.codec.decode(&data).map_err(|cause| LPopError::Decode {
+            key: key.into(),
+            cause: cause.into(),
+        })
+    }
+
+    async fn lrpop_bytes(&mut self, key: &str, right: bool) -> Result<Vec<u8>, LPopError> {
+        let data = self.lrpop_raw(key, right).await?;
+        codec::payload(data).map_err(|cause| LPopError::Decode {
+            key: key.into(),
+            cause: cause.into(),
+        })
+    }
+
     pub async fn lpop<T>(&mut self, key: &str) -> Result<T, LPopError>
     where T: DeserializeOwned {
         self.lrpop(key, false).await
@@ -327,9 +1061,15 @@ impl Kadis {
         self.lrpop(key, true).await
     }
 
-    pub async fn lpos_rank<T>(&mut self, key: &str, item: T, rank: i32) -> Result<Option<usize>, LPosError>
-    where T: Serialize {
-        let item = bincode::serialize(&item).unwrap();
+    pub async fn lpop_bytes(&mut self, key: &str) -> Result<Vec<u8>, LPopError> {
+        self.lrpop_bytes(key, false).await
+    }
+
+    pub async fn rpop_bytes(&mut self, key: &str) -> Result<Vec<u8>, LPopError> {
+        self.lrpop_bytes(key, true).await
+    }
+
+    async fn lpos_raw(&mut self, key: &str, item: Vec<u8>, rank: i32) -> Result<Option<usize>, LPosError> {
         let cmd = Cmd::List(ListCmd::Pos(key, item, rank));
         match handle_cmd(&mut self.node, cmd).await {
             CmdResult::List(ListResult::Pos(res)) => res,
@@ -337,14 +1077,29 @@ impl Kadis {
         }
     }
 
+    pub async fn lpos_rank<T>(&mut self, key: &str, item: T, rank: i32) -> Result<Option<usize>, LPosError>
+    where T: Serialize {
+        let item = self.codec.encode(&item).map_err(|cause| LPosError::Encode {
+            key: key.into(),
+            cause: cause.into(),
+        })?;
+        self.lpos_raw(key, item, rank).await
+    }
+
     pub async fn lpos<T>(&mut self, key: &str, item: T) -> Result<Option<usize>, LPosError>
     where T: Serialize {
         self.lpos_rank(key, item, 1).await
     }
 
-    async fn lrpush<T>(&mut self, key: &str, item: T, right: bool) -> Result<(), LPushError>
-    where T: Serialize {
-        let item = bincode::serialize(&item).unwrap();
+    pub async fn lpos_rank_bytes(&mut self, key: &str, item: &[u8], rank: i32) -> Result<Option<usize>, LPosError> {
+        self.lpos_raw(key, codec::raw(item), rank).await
+    }
+
+    pub async fn lpos_bytes(&mut self, key: &str, item: &[u8]) -> Result<Option<usize>, LPosError> {
+        self.lpos_rank_bytes(key, item, 1).await
+    }
+
+    async fn lrpush_raw(&mut self, key: &str, item: Vec<u8>, right: bool) -> Result<(), LPushError> {
         let cmd = Cmd::List(ListCmd::Push(key, item, right));
         match handle_cmd(&mut self.node, cmd).await {
             CmdResult::List(ListResult::Push(res)) => res,
@@ -352,6 +1107,15 @@ impl Kadis {
         }
     }
 
+    async fn lrpush<T>(&mut self, key: &str, item: T, right: bool) -> Result<(), LPushError>
+    where T: Serialize {
+        let item = self.codec.encode(&item).map_err(|cause| LPushError::Encode {
+            key: key.into(),
+            cause: cause.into(),
+        })?;
+        self.lrpush_raw(key, item, right).await
+    }
+
     pub async fn lpush<T>(&mut self, key: &str, item: T) -> Result<(), LPushError>
     where T: Serialize {
         self.lrpush(key, item, false).await
@@ -362,9 +1126,15 @@ impl Kadis {
         self.lrpush(key, item, true).await
     }
 
-    async fn lrpush_exists<T>(&mut self, key: &str, item: T, right: bool) -> Result<(), LPushError>
-    where T: Serialize {
-        let item = bincode::serialize(&item).unwrap();
+    pub async fn lpush_bytes(&mut self, key: &str, item: &[u8]) -> Result<(), LPushError> {
+        self.lrpush_raw(key, codec::raw(item), false).await
+    }
+
+    pub async fn rpush_bytes(&mut self, key: &str, item: &[u8]) -> Result<(), LPushError> {
+        self.lrpush_raw(key, codec::raw(item), true).await
+    }
+
+    async fn lrpush_exists_raw(&mut self, key: &str, item: Vec<u8>, right: bool) -> Result<(), LPushError> {
         let cmd = Cmd::List(ListCmd::PushX(key, item, right));
         match handle_cmd(&mut self.node, cmd).await {
             CmdResult::List(ListResult::PushX(res)) => res,
@@ -372,6 +1142,15 @@ impl Kadis {
         }
     }
 
+    async fn lrpush_exists<T>(&mut self, key: &str, item: T, right: bool) -> Result<(), LPushError>
+    where T: Serialize {
+        let item = self.codec.encode(&item).map_err(|cause| LPushError::Encode {
+            key: key.into(),
+            cause: cause.into(),
+        })?;
+        self.lrpush_exists_raw(key, item, right).await
+    }
+
     pub async fn lpush_exists<T>(&mut self, key: &str, item: T) -> Result<(), LPushError>
     where T: Serialize {
         self.lrpush_exists(key, item, false).await
@@ -382,35 +1161,131 @@ impl Kadis {
         self.lrpush_exists(key, item, true).await
     }
 
+    pub async fn lpush_exists_bytes(&mut self, key: &str, item: &[u8]) -> Result<(), LPushError> {
+        self.lrpush_exists_raw(key, codec::raw(item), false).await
+    }
+
+    pub async fn rpush_exists_bytes(&mut self, key: &str, item: &[u8]) -> Result<(), LPushError> {
+        self.lrpush_exists_raw(key, codec::raw(item), true).await
+    }
+
+    async fn lrange_raw(&mut self, key: &str, start: isize, stop: isize) -> Result<(usize, Vec<Vec<u8>>), LRangeError> {
+        let cmd = Cmd::List(ListCmd::Range(key, start, stop));
+        match handle_cmd(&mut self.node, cmd).await {
+            CmdResult::List(ListResult::Range(res)) => res,
+            _ => unreachable!(),
+        }
+    }
+
     pub async fn lrange<T>(&mut self, key: &str, start: isize, stop: isize) -> Result<Vec<T>, LRangeError>
     where T: DeserializeOwned {
-        let cmd = Cmd::List(ListCmd::Range(key, start, stop));
+        let (first, data) = self.lrange_raw(key, start, stop).await?;
+        data.iter()
+            .enumerate()
+            .map(|(offset, d)| self.codec.decode(d).map_err(|cause| LRangeError::Decode {
+                key: key.into(),
+                index: first + offset,
+                cause: cause.into(),
+            }))
+            .collect()
+    }
+
+    pub async fn lrange_bytes(&mut self, key: &str, start: isize, stop: isize) -> Result<Vec<Vec<u8>>, LRangeError> {
+        let (first, data) = self.lrange_raw(key, start, stop).await?;
+        data.into_iter()
+            .enumerate()
+            .map(|(offset, d)| codec::payload(d).map_err(|cause| LRangeError::Decode {
+                key: key.into(),
+                index: first + offset,
+                cause: cause.into(),
+            }))
+            .collect()
+    }
+
+    async fn lrem_raw(&mut self, key: &str, index: isize) -> Result<Vec<u8>, LRemError> {
+        let cmd = Cmd::List(ListCmd::Rem(key, index));
         match handle_cmd(&mut self.node, cmd).await {
-            CmdResult::List(ListResult::Range(res)) => match res {
-                Ok(data) => Ok(data.iter()
-                    .map(|d| bincode::deserialize(d).unwrap())
-                    .collect()),
-                Err(err) => Err(err),
-            },
+            CmdResult::List(ListResult::Rem(res)) => res,
             _ => unreachable!(),
         }
     }
 
     pub async fn lrem<T>(&mut self, key: &str, index: isize) -> Result<T, LRemError>
     where T: DeserializeOwned {
-        let cmd = Cmd::List(ListCmd::Rem(key, index));
+        let data = self.lrem_raw(key, index).await?;
+        self.codec.decode(&data).map_err(|cause| LRemError::Decode {
+            key: key.into(),
+            index,
+            cause: cause.into(),
+        })
+    }
+
+    pub async fn lrem_bytes(&mut self, key: &str, index: isize) -> Result<Vec<u8>, LRemError> {
+        let data = self.lrem_raw(key, index).await?;
+        codec::payload(data).map_err(|cause| LRemError::Decode {
+            key: key.into(),
+            index,
+            cause: cause.into(),
+        })
+    }
+
+    async fn lscan_raw(&mut self, key: &str, scan: &Scan<'_>) -> Result<(usize, Vec<Vec<u8>>), LScanError> {
+        let cmd = Cmd::List(ListCmd::Scan(key, scan.cursor, scan.count));
         match handle_cmd(&mut self.node, cmd).await {
-            CmdResult::List(ListResult::Rem(res)) => match res {
-                Ok(data) => Ok(bincode::deserialize(&data).unwrap()),
-                Err(err) => Err(err),
-            },
+            CmdResult::List(ListResult::Scan(res)) => res,
             _ => unreachable!(),
         }
     }
 
-    pub async fn lset<T>(&mut self, key: &str, index: isize, item: T) -> Result<(), LSetError>
-    where T: Serialize {
-        let item = bincode::serialize(&item).unwrap();
+    async fn lscan_decoded<T>(&mut self, key: &str, scan: &Scan<'_>, decode: fn(&Kadis, Vec<u8>) -> Result<T, CodecError>) -> Result<(usize, Vec<T>), LScanError> {
+        let (cursor, page) = self.lscan_raw(key, scan).await?;
+        let page = page.into_iter()
+            .enumerate()
+            .map(|(offset, d)| decode(self, d).map_err(|cause| LScanError::Decode {
+                key: key.into(),
+                index: scan.cursor + offset,
+                cause: cause.into(),
+            }))
+            .collect::<Result<_, _>>()?;
+        Ok((cursor, page))
+    }
+
+    /// Reads one page of the list at `key`, returning it with the cursor of
+    /// the next page, which is 0 once the whole list has been read.
+    pub async fn lscan_page<T>(&mut self, key: &str, scan: &Scan<'_>) -> Result<(usize, Vec<T>), LScanError>
+    where T: DeserializeOwned {
+        self.lscan_decoded(key, scan, |kadis, d| kadis.codec.decode(&d)).await
+    }
+
+    pub async fn lscan_page_bytes(&mut self, key: &str, scan: &Scan<'_>) -> Result<(usize, Vec<Vec<u8>>), LScanError> {
+        self.lscan_decoded(key, scan, |_, d| codec::payload(d)).await
+    }
+
+    fn lscan_stream<'a, T>(&self, key: &'a str, scan: Scan<'a>, decode: fn(&Kadis, Vec<u8>) -> Result<T, CodecError>) -> impl Stream<Item = Result<T, LScanError>> + 'a
+    where T: 'a {
+        stream::unfold((self.clone(), Some(scan)), move |(mut kadis, scan)| async move {
+            let scan = scan?;
+            let (next, page) = match kadis.lscan_decoded(key, &scan, decode).await {
+                Ok((0, page)) => (None, page.into_iter().map(Ok).collect()),
+                Ok((cursor, page)) => (Some(scan.cursor(cursor)), page.into_iter().map(Ok).collect()),
+                Err(err) => (None, vec![Err(err)]),
+            };
+            Some((stream::iter(page), (kadis, next)))
+        })
+        .flatten()
+    }
+
+    /// Streams the items of the list at `key`, holding one page at a time.
+    pub fn lscan<'a, T>(&self, key: &'a str, scan: Scan<'a>) -> impl Stream<Item = Result<T, LScanError>> + 'a
+    where T: DeserializeOwned + 'a {
+        self.lscan_stream(key, scan, |kadis, d| kadis.codec.decode(&d))
+    }
+
+    pub fn lscan_bytes<'a>(&self, key: &'a str, scan: Scan<'a>) -> impl Stream<Item = Result<Vec<u8>, LScanError>> + 'a {
+        self.lscan_stream(key, scan, |_, d| codec::payload(d))
+    }
+
+    async fn lset_raw(&mut self, key: &str, index: isize, item: Vec<u8>) -> Result<(), LSetError> {
         let cmd = Cmd::List(ListCmd::Set(key, index, item));
         match handle_cmd(&mut self.node, cmd).await {
             CmdResult::List(ListResult::Set(res)) => res,
@@ -418,6 +1293,20 @@ impl Kadis {
         }
     }
 
+    pub async fn lset<T>(&mut self, key: &str, index: isize, item: T) -> Result<(), LSetError>
+    where T: Serialize {
+        let item = self.codec.encode(&item).map_err(|cause| LSetError::Encode {
+            key: key.into(),
+            index,
+            cause: cause.into(),
+        })?;
+        self.lset_raw(key, index, item).await
+    }
+
+    pub async fn lset_bytes(&mut self, key: &str, index: isize, item: &[u8]) -> Result<(), LSetError> {
+        self.lset_raw(key, index, codec::raw(item)).await
+    }
+
     pub async fn ltrim<T>(&mut self, key: &str, start: isize, stop: isize) -> Result<(), LTrimError> {
         let cmd = Cmd::List(ListCmd::Trim(key, start, stop));
         match handle_cmd(&mut self.node, cmd).await {
@@ -426,11 +1315,3 @@ impl Kadis {
         }
     }
 }
-
-#[cfg(test)]
-fn main() {
-    let _ = KadisBuilder::default().port(5130).init().unwrap();
-    for _ in 0..30 {
-        KadisBuilder::default().bootstraps(&["/ip4/0.0.0.0/tcp/5130"]).init().unwrap();
-    }
-}
@@ -14,6 +14,10 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
+use std::error::Error;
+
+use crate::{impl_error, ErrorCode};
+
 #[derive(Debug)]
 pub enum LCollectError {
 	KeyNotFound {
@@ -37,8 +41,41 @@ pub enum LCollectError {
 		key: String,
 		index: usize,
 	},
+	Decode {
+		key: String,
+		index: usize,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	Invalid {
+		key: String,
+		index: usize,
+	},
+	PermissionDenied {
+		key: String,
+	},
+	Partial {
+		key: String,
+		errors: Vec<LCollectError>,
+	},
 }
 
+impl_error!(LCollectError => LCollect {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	NotFound { key, index } => NotFound, "item {index} not found in list `{key}`",
+	QuorumFailed { key, index } => QuorumFailed, "quorum failed for item {index} of list `{key}`",
+	Timeout { key, index } => Timeout, "request timed out for item {index} of list `{key}`",
+	Decode { key, index, cause } => Decode, "could not decode item {index} of list `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	Invalid { key, index } => Invalid, "invalid record for item {index} of list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+	Partial { key } => Partial, "some items of list `{key}` could not be read",
+});
+
 #[derive(Debug)]
 pub enum LIndexError {
 	KeyNotFound {
@@ -67,8 +104,37 @@ pub enum LIndexError {
 		key: String,
 		index: isize,
 	},
+	Decode {
+		key: String,
+		index: isize,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	Invalid {
+		key: String,
+		index: isize,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(LIndexError => LIndex {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	OutOfBounds { key, index, len } => OutOfBounds, "index {index} is out of bounds for list `{key}` of length {len}",
+	NotFound { key, index } => NotFound, "item {index} not found in list `{key}`",
+	QuorumFailed { key, index } => QuorumFailed, "quorum failed for item {index} of list `{key}`",
+	Timeout { key, index } => Timeout, "request timed out for item {index} of list `{key}`",
+	Decode { key, index, cause } => Decode, "could not decode item {index} of list `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	Invalid { key, index } => Invalid, "invalid record for item {index} of list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+});
+
 #[derive(Debug)]
 pub enum LInsertError {
 	KeyNotFound {
@@ -93,8 +159,40 @@ pub enum LInsertError {
 		key: String,
 		index: isize,
 	},
+	Rejected {
+		key: String,
+		index: isize,
+	},
+	KeyRejected {
+		key: String,
+	},
+	Encode {
+		key: String,
+		index: isize,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(LInsertError => LInsert {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	OutOfBounds { key, index, len } => OutOfBounds, "index {index} is out of bounds for list `{key}` of length {len}",
+	QuorumFailed { key, index } => QuorumFailed, "quorum failed for item {index} of list `{key}`",
+	Timeout { key, index } => Timeout, "request timed out for item {index} of list `{key}`",
+	Rejected { key, index } => Rejected, "record for item {index} of list `{key}` rejected by local store",
+	KeyRejected { key } => Rejected, "record for list `{key}` rejected by local store",
+	Encode { key, index, cause } => Encode, "could not encode item {index} of list `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+});
+
 #[derive(Debug)]
 pub enum LPopError {
 	KeyNotFound {
@@ -121,8 +219,40 @@ pub enum LPopError {
 		key: String,
 		index: usize,
 	},
+	KeyRejected {
+		key: String,
+	},
+	Decode {
+		key: String,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	Invalid {
+		key: String,
+		index: usize,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(LPopError => LPop {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	EmptyList { key } => EmptyList, "list `{key}` is empty",
+	NotFound { key, index } => NotFound, "item {index} not found in list `{key}`",
+	QuorumFailed { key, index } => QuorumFailed, "quorum failed for item {index} of list `{key}`",
+	Timeout { key, index } => Timeout, "request timed out for item {index} of list `{key}`",
+	KeyRejected { key } => Rejected, "record for list `{key}` rejected by local store",
+	Decode { key, cause } => Decode, "could not decode item popped from list `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	Invalid { key, index } => Invalid, "invalid record for item {index} of list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+});
+
 #[derive(Debug)]
 pub enum LPosError {
 	KeyNotFound {
@@ -149,8 +279,36 @@ pub enum LPosError {
 		key: String,
 		index: usize,
 	},
+	Encode {
+		key: String,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	Invalid {
+		key: String,
+		index: usize,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(LPosError => LPos {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	RankZero { key } => RankZero, "rank of zero given for list `{key}`",
+	NotFound { key, index } => NotFound, "item {index} not found in list `{key}`",
+	QuorumFailed { key, index } => QuorumFailed, "quorum failed for item {index} of list `{key}`",
+	Timeout { key, index } => Timeout, "request timed out for item {index} of list `{key}`",
+	Encode { key, cause } => Encode, "could not encode item to find in list `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	Invalid { key, index } => Invalid, "invalid record for item {index} of list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+});
+
 #[derive(Debug)]
 pub enum LLenError {
 	KeyNotFound {
@@ -162,8 +320,22 @@ pub enum LLenError {
 	KeyTimeout {
 		key: String,
 	},
+	KeyInvalid {
+		key: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(LLenError => LLen {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+});
+
 #[derive(Debug)]
 pub enum LPushError {
 	KeyNotFound {
@@ -181,8 +353,37 @@ pub enum LPushError {
 	Timeout {
 		key: String,
 	},
+	KeyRejected {
+		key: String,
+	},
+	Rejected {
+		key: String,
+	},
+	Encode {
+		key: String,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(LPushError => LPush {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	QuorumFailed { key } => QuorumFailed, "quorum failed storing item in list `{key}`",
+	Timeout { key } => Timeout, "request timed out storing item in list `{key}`",
+	KeyRejected { key } => Rejected, "record for list `{key}` rejected by local store",
+	Rejected { key } => Rejected, "record for item of list `{key}` rejected by local store",
+	Encode { key, cause } => Encode, "could not encode item for list `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+});
+
 #[derive(Debug)]
 pub enum LRangeError {
 	KeyNotFound {
@@ -211,8 +412,42 @@ pub enum LRangeError {
 		key: String,
 		index: usize,
 	},
+	Decode {
+		key: String,
+		index: usize,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	Invalid {
+		key: String,
+		index: usize,
+	},
+	PermissionDenied {
+		key: String,
+	},
+	Partial {
+		key: String,
+		errors: Vec<LRangeError>,
+	},
 }
 
+impl_error!(LRangeError => LRange {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	OutOfBounds { key, index, len } => OutOfBounds, "index {index} is out of bounds for list `{key}` of length {len}",
+	NotFound { key, index } => NotFound, "item {index} not found in list `{key}`",
+	QuorumFailed { key, index } => QuorumFailed, "quorum failed for item {index} of list `{key}`",
+	Timeout { key, index } => Timeout, "request timed out for item {index} of list `{key}`",
+	Decode { key, index, cause } => Decode, "could not decode item {index} of list `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	Invalid { key, index } => Invalid, "invalid record for item {index} of list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+	Partial { key } => Partial, "some items of list `{key}` could not be read",
+});
+
 #[derive(Debug)]
 pub enum LRemError {
 	KeyNotFound {
@@ -241,8 +476,41 @@ pub enum LRemError {
 		key: String,
 		index: isize,
 	},
+	KeyRejected {
+		key: String,
+	},
+	Decode {
+		key: String,
+		index: isize,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	Invalid {
+		key: String,
+		index: isize,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(LRemError => LRem {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	OutOfBounds { key, index, len } => OutOfBounds, "index {index} is out of bounds for list `{key}` of length {len}",
+	NotFound { key, index } => NotFound, "item {index} not found in list `{key}`",
+	QuorumFailed { key, index } => QuorumFailed, "quorum failed for item {index} of list `{key}`",
+	Timeout { key, index } => Timeout, "request timed out for item {index} of list `{key}`",
+	KeyRejected { key } => Rejected, "record for list `{key}` rejected by local store",
+	Decode { key, index, cause } => Decode, "could not decode item {index} of list `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	Invalid { key, index } => Invalid, "invalid record for item {index} of list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+});
+
 #[derive(Debug)]
 pub enum LSetError {
 	KeyNotFound {
@@ -271,8 +539,37 @@ pub enum LSetError {
 		key: String,
 		index: isize,
 	},
+	Rejected {
+		key: String,
+		index: isize,
+	},
+	Encode {
+		key: String,
+		index: isize,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(LSetError => LSet {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	OutOfBounds { key, index, len } => OutOfBounds, "index {index} is out of bounds for list `{key}` of length {len}",
+	NotFound { key, index } => NotFound, "item {index} not found in list `{key}`",
+	QuorumFailed { key, index } => QuorumFailed, "quorum failed for item {index} of list `{key}`",
+	Timeout { key, index } => Timeout, "request timed out for item {index} of list `{key}`",
+	Rejected { key, index } => Rejected, "record for item {index} of list `{key}` rejected by local store",
+	Encode { key, index, cause } => Encode, "could not encode item {index} of list `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+});
+
 #[derive(Debug)]
 pub enum LTrimError {
 	KeyNotFound {
@@ -297,8 +594,87 @@ pub enum LTrimError {
 		key: String,
 		index: isize,
 	},
+	KeyRejected {
+		key: String,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	PermissionDenied {
+		key: String,
+	},
 }
 
+impl_error!(LTrimError => LTrim {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	OutOfBounds { key, index, len } => OutOfBounds, "index {index} is out of bounds for list `{key}` of length {len}",
+	QuorumFailed { key, index } => QuorumFailed, "quorum failed for item {index} of list `{key}`",
+	Timeout { key, index } => Timeout, "request timed out for item {index} of list `{key}`",
+	KeyRejected { key } => Rejected, "record for list `{key}` rejected by local store",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+});
+
+#[derive(Debug)]
+pub enum LScanError {
+	KeyNotFound {
+		key: String,
+	},
+	KeyQuorumFailed {
+		key: String,
+	},
+	KeyTimeout {
+		key: String,
+	},
+	NotFound {
+		key: String,
+		index: usize,
+	},
+	QuorumFailed {
+		key: String,
+		index: usize,
+	},
+	Timeout {
+		key: String,
+		index: usize,
+	},
+	Decode {
+		key: String,
+		index: usize,
+		cause: Box<dyn Error + Send + Sync>,
+	},
+	KeyInvalid {
+		key: String,
+	},
+	Invalid {
+		key: String,
+		index: usize,
+	},
+	PermissionDenied {
+		key: String,
+	},
+	Partial {
+		key: String,
+		errors: Vec<LScanError>,
+	},
+}
+
+impl_error!(LScanError => LScan {
+	KeyNotFound { key } => NotFound, "list `{key}` not found",
+	KeyQuorumFailed { key } => QuorumFailed, "quorum failed for list `{key}`",
+	KeyTimeout { key } => Timeout, "request timed out for list `{key}`",
+	NotFound { key, index } => NotFound, "item {index} not found in list `{key}`",
+	QuorumFailed { key, index } => QuorumFailed, "quorum failed for item {index} of list `{key}`",
+	Timeout { key, index } => Timeout, "request timed out for item {index} of list `{key}`",
+	Decode { key, index, cause } => Decode, "could not decode item {index} of list `{key}`: {cause}",
+	KeyInvalid { key } => Invalid, "invalid record for list `{key}`",
+	Invalid { key, index } => Invalid, "invalid record for item {index} of list `{key}`",
+	PermissionDenied { key } => PermissionDenied, "permission denied for list `{key}`",
+	Partial { key } => Partial, "some items of list `{key}` could not be read",
+});
+
 pub enum ListResult {
 	Collect(Result<Vec<Vec<u8>>, LCollectError>),
 	Index(Result<Vec<u8>, LIndexError>),
@@ -308,8 +684,31 @@ pub enum ListResult {
 	Pos(Result<Option<usize>, LPosError>),
 	Push(Result<(), LPushError>),
 	PushX(Result<(), LPushError>),
-	Range(Result<Vec<Vec<u8>>, LRangeError>),
+	// Along with the list index of the first item
+	Range(Result<(usize, Vec<Vec<u8>>), LRangeError>),
 	Rem(Result<Vec<u8>, LRemError>),
+	Scan(Result<(usize, Vec<Vec<u8>>), LScanError>),
 	Set(Result<(), LSetError>),
 	Trim(Result<(), LTrimError>),
 }
+
+impl ListResult {
+	/// Code of the error the command failed with, if it did.
+	pub fn code(&self) -> Option<ErrorCode> {
+		match self {
+			ListResult::Collect(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Index(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Insert(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Len(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Pop(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Pos(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Push(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::PushX(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Range(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Rem(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Scan(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Set(res) => res.as_ref().err().map(|err| err.code()),
+			ListResult::Trim(res) => res.as_ref().err().map(|err| err.code()),
+		}
+	}
+}
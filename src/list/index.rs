@@ -0,0 +1,333 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::convert::TryInto;
+
+use crate::keys;
+use crate::node::{GetError, Node, PutError};
+use crate::util::{decode_list, encode_list};
+
+/// Most item IDs kept in one segment record.
+pub const SEGMENT_SIZE: usize = 256;
+
+// Heads share the first byte with nothing else stored under a list key
+const HEAD: u8 = 0xfe;
+const VERSION: u8 = 1;
+
+struct Segment {
+	id: u64,
+	len: usize,
+	// Loaded on demand, since most commands only touch a few segments
+	ids: Option<Vec<String>>,
+	dirty: bool,
+}
+
+/// Index of a list, split into a head record holding the length of every
+/// segment and the segment records holding item IDs, like a quicklist.
+pub struct ListIndex {
+	key: String,
+	next_id: u64,
+	segments: Vec<Segment>,
+	removed: Vec<u64>,
+}
+
+fn read<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], GetError> {
+	if data.len() < len {
+		return Err(GetError::Invalid);
+	}
+	let (head, tail) = data.split_at(len);
+	*data = tail;
+	Ok(head)
+}
+
+fn read_u64(data: &mut &[u8]) -> Result<u64, GetError> {
+	Ok(u64::from_le_bytes(read(data, 8)?.try_into().map_err(|_| GetError::Invalid)?))
+}
+
+fn read_u32(data: &mut &[u8]) -> Result<usize, GetError> {
+	Ok(u32::from_le_bytes(read(data, 4)?.try_into().map_err(|_| GetError::Invalid)?) as usize)
+}
+
+impl ListIndex {
+	pub fn new(key: &str) -> Self {
+		Self {
+			key: key.into(),
+			next_id: 0,
+			segments: Vec::new(),
+			removed: Vec::new(),
+		}
+	}
+
+	pub async fn load(node: &mut Node, key: &str) -> Result<Self, GetError> {
+		let data = node.get(&keys::list_items(key)).await?;
+		if data.first() != Some(&HEAD) {
+			return Ok(Self::from_flat(key, decode_list(data)?));
+		}
+		Self::decode_head(key, &data)
+	}
+
+	fn decode_head(key: &str, data: &[u8]) -> Result<Self, GetError> {
+		if data.get(1) != Some(&VERSION) {
+			log::warn!("Unknown list head version {:?}", data.get(1));
+			return Err(GetError::Invalid);
+		}
+
+		let mut rest = &data[2..];
+		let next_id = read_u64(&mut rest)?;
+		let count = read_u32(&mut rest)?;
+		let mut segments = Vec::with_capacity(count.min(rest.len() / 12));
+		for _ in 0..count {
+			segments.push(Segment {
+				id: read_u64(&mut rest)?,
+				len: read_u32(&mut rest)?,
+				ids: None,
+				dirty: false,
+			});
+		}
+		if !rest.is_empty() {
+			return Err(GetError::Invalid);
+		}
+
+		Ok(Self {
+			key: key.into(),
+			next_id,
+			segments,
+			removed: Vec::new(),
+		})
+	}
+
+	pub async fn load_or_new(node: &mut Node, key: &str) -> Result<Self, GetError> {
+		match Self::load(node, key).await {
+			Err(GetError::NotFound) => Ok(Self::new(key)),
+			res => res,
+		}
+	}
+
+	// Lists written before segments existed are split up on their next write
+	fn from_flat(key: &str, ids: Vec<String>) -> Self {
+		let mut index = Self::new(key);
+		for chunk in ids.chunks(SEGMENT_SIZE) {
+			index.push_segment(index.segments.len(), chunk.to_vec());
+		}
+		index
+	}
+
+	fn push_segment(&mut self, position: usize, ids: Vec<String>) {
+		let segment = Segment {
+			id: self.next_id,
+			len: ids.len(),
+			ids: Some(ids),
+			dirty: true,
+		};
+		self.next_id += 1;
+		self.segments.insert(position, segment);
+	}
+
+	pub fn len(&self) -> usize {
+		self.segments.iter().map(|segment| segment.len).sum()
+	}
+
+	/// Finds the segment holding `index` and the offset within it.
+	fn locate(&self, index: usize) -> (usize, usize) {
+		let mut offset = index;
+		for (position, segment) in self.segments.iter().enumerate() {
+			if offset < segment.len {
+				return (position, offset);
+			}
+			offset -= segment.len;
+		}
+		(self.segments.len(), offset)
+	}
+
+	async fn segment(&mut self, node: &mut Node, position: usize) -> Result<&mut Vec<String>, GetError> {
+		let segment = &mut self.segments[position];
+		if segment.ids.is_none() {
+			let ids = decode_list(node.get(&keys::list_segment(&self.key, segment.id)).await?)?;
+			if ids.len() != segment.len {
+				log::warn!("Segment {} of list {} has the wrong length", segment.id, self.key);
+				return Err(GetError::Invalid);
+			}
+			segment.ids = Some(ids);
+		}
+		Ok(segment.ids.get_or_insert_with(Vec::new))
+	}
+
+	fn changed(&mut self, position: usize) {
+		let segment = &mut self.segments[position];
+		segment.len = segment.ids.as_ref().map_or(0, Vec::len);
+		segment.dirty = true;
+		if segment.len == 0 {
+			self.removed.push(segment.id);
+			self.segments.remove(position);
+		}
+	}
+
+	pub async fn get(&mut self, node: &mut Node, index: usize) -> Result<String, GetError> {
+		let (position, offset) = self.locate(index);
+		if position == self.segments.len() {
+			return Err(GetError::NotFound);
+		}
+		let ids = self.segment(node, position).await?;
+		Ok(ids[offset].clone())
+	}
+
+	/// Item IDs from `start` to `stop` inclusive, fetching only the segments they are in.
+	pub async fn range(&mut self, node: &mut Node, start: usize, stop: usize) -> Result<Vec<String>, GetError> {
+		let mut range = Vec::new();
+		let mut first = 0;
+		for position in 0..self.segments.len() {
+			let len = self.segments[position].len;
+			if first + len > start && first <= stop {
+				let ids = self.segment(node, position).await?;
+				let from = start.saturating_sub(first);
+				let to = (stop - first).min(len - 1);
+				range.extend_from_slice(&ids[from..=to]);
+			}
+			first += len;
+		}
+		Ok(range)
+	}
+
+	pub async fn all(&mut self, node: &mut Node) -> Result<Vec<String>, GetError> {
+		match self.len() {
+			0 => Ok(Vec::new()),
+			len => self.range(node, 0, len - 1).await,
+		}
+	}
+
+	/// Inserts `id` so that it ends up at `index`, which may equal the length.
+	pub async fn insert(&mut self, node: &mut Node, index: usize, id: String) -> Result<(), GetError> {
+		// Pushing onto a full end segment starts a new one without loading it
+		let len = self.len();
+		if index == 0 && !matches!(self.segments.first(), Some(segment) if segment.len < SEGMENT_SIZE) {
+			self.push_segment(0, vec![id]);
+			return Ok(());
+		}
+		if index == len && !matches!(self.segments.last(), Some(segment) if segment.len < SEGMENT_SIZE) {
+			self.push_segment(self.segments.len(), vec![id]);
+			return Ok(());
+		}
+
+		let (position, offset) = match self.locate(index) {
+			// Appending goes to the end of the last segment
+			(position, _) if position == self.segments.len() => (position - 1, self.segments[position - 1].len),
+			located => located,
+		};
+		let ids = self.segment(node, position).await?;
+		ids.insert(offset, id);
+		if ids.len() > SEGMENT_SIZE {
+			let tail = ids.split_off(ids.len() / 2);
+			self.push_segment(position + 1, tail);
+		}
+		self.changed(position);
+		Ok(())
+	}
+
+	pub async fn remove(&mut self, node: &mut Node, index: usize) -> Result<String, GetError> {
+		let (position, offset) = self.locate(index);
+		if position == self.segments.len() {
+			return Err(GetError::NotFound);
+		}
+		let id = self.segment(node, position).await?.remove(offset);
+		self.changed(position);
+		Ok(id)
+	}
+
+	/// Keeps only the items from `start` to `stop` inclusive and returns the
+	/// IDs of the items that were dropped.
+	pub async fn trim(&mut self, node: &mut Node, start: usize, stop: usize) -> Result<Vec<String>, GetError> {
+		let mut dropped = Vec::new();
+		let mut first = 0;
+		let mut position = 0;
+		while position < self.segments.len() {
+			let len = self.segments[position].len;
+			let ids = self.segment(node, position).await?;
+			let keep_from = start.saturating_sub(first).min(len);
+			let keep_to = if stop < first { 0 } else { (stop - first + 1).min(len) };
+
+			if keep_from >= keep_to {
+				dropped.append(ids);
+			} else {
+				dropped.extend(ids.drain(keep_to..));
+				dropped.extend(ids.drain(..keep_from));
+			}
+
+			first += len;
+			if ids.len() != len {
+				let empty = ids.is_empty();
+				self.changed(position);
+				if empty {
+					continue;
+				}
+			}
+			position += 1;
+		}
+		Ok(dropped)
+	}
+
+	fn encode_head(&self) -> Vec<u8> {
+		let mut data = Vec::with_capacity(14 + self.segments.len() * 12);
+		data.push(HEAD);
+		data.push(VERSION);
+		data.extend_from_slice(&self.next_id.to_le_bytes());
+		data.extend_from_slice(&(self.segments.len() as u32).to_le_bytes());
+		for segment in &self.segments {
+			data.extend_from_slice(&segment.id.to_le_bytes());
+			data.extend_from_slice(&(segment.len as u32).to_le_bytes());
+		}
+		data
+	}
+
+	/// Writes changed segments before the head, so the head never points at
+	/// segments that are not stored yet.
+	pub async fn save(&mut self, node: &mut Node) -> Result<(), PutError> {
+		for segment in self.segments.iter_mut().filter(|segment| segment.dirty) {
+			let ids = segment.ids.as_deref().unwrap_or_default();
+			node.put(&keys::list_segment(&self.key, segment.id), encode_list(ids)).await?;
+			segment.dirty = false;
+		}
+
+		node.put(&keys::list_items(&self.key), self.encode_head()).await?;
+
+		for id in self.removed.drain(..) {
+			node.remove(&keys::list_segment(&self.key, id)).await;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn head_round_trip() {
+		let ids: Vec<String> = (0..SEGMENT_SIZE * 2 + 10).map(|i| i.to_string()).collect();
+		let index = ListIndex::from_flat("a", ids);
+		assert_eq!(index.segments.len(), 3);
+		assert_eq!(index.locate(SEGMENT_SIZE + 1), (1, 1));
+		assert_eq!(index.locate(index.len()), (3, 0));
+
+		let decoded = ListIndex::decode_head("a", &index.encode_head()).unwrap();
+		assert_eq!(decoded.len(), SEGMENT_SIZE * 2 + 10);
+		assert_eq!(decoded.next_id, 3);
+		assert!(decoded.segments.iter().all(|segment| segment.ids.is_none()));
+
+		let mut truncated = index.encode_head();
+		truncated.pop();
+		assert!(ListIndex::decode_head("a", &truncated).is_err());
+	}
+}
@@ -16,17 +16,21 @@
 
 use std::str;
 
+use tracing::{field, Instrument};
 use uuid::Uuid;
 
-use crate::{get_list, get_list_exists, join_list};
+use crate::{key_get, key_put};
+use crate::acl::Permission;
+use crate::keys;
 use crate::node::{Node, GetError, PutError};
-use crate::util::split_list;
 
 mod error;
+mod index;
 #[cfg(test)]
 mod tests;
 
 pub use error::*;
+use index::ListIndex;
 
 pub enum ListCmd<'a> {
 	Collect(&'a str),
@@ -39,12 +43,73 @@ pub enum ListCmd<'a> {
 	PushX(&'a str, Vec<u8>, bool),
 	Range(&'a str, isize, isize),
 	Rem(&'a str, isize),
+	Scan(&'a str, usize, usize),
 	Set(&'a str, isize, Vec<u8>),
 	Trim(&'a str, isize, isize),
 }
 
 use ListCmd::*;
 
+impl ListCmd<'_> {
+	pub fn key(&self) -> &str {
+		match self {
+			Collect(key) => key,
+			Index(key, ..) => key,
+			Insert(key, ..) => key,
+			Len(key) => key,
+			Pop(key, ..) => key,
+			Pos(key, ..) => key,
+			Push(key, ..) => key,
+			PushX(key, ..) => key,
+			Range(key, ..) => key,
+			Rem(key, ..) => key,
+			Scan(key, ..) => key,
+			Set(key, ..) => key,
+			Trim(key, ..) => key,
+		}
+	}
+
+	/// Name of the command, as used by the `Kadis` method running it.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Collect(..) => "lcollect",
+			Index(..) => "lindex",
+			Insert(..) => "linsert",
+			Len(..) => "llen",
+			Pop(_, false) => "lpop",
+			Pop(_, true) => "rpop",
+			Pos(..) => "lpos",
+			Push(_, _, false) => "lpush",
+			Push(_, _, true) => "rpush",
+			PushX(_, _, false) => "lpush_exists",
+			PushX(_, _, true) => "rpush_exists",
+			Range(..) => "lrange",
+			Rem(..) => "lrem",
+			Scan(..) => "lscan",
+			Set(..) => "lset",
+			Trim(..) => "ltrim",
+		}
+	}
+
+	pub fn permission(&self) -> Permission {
+		match self {
+			Collect(..) => Permission::Read,
+			Index(..) => Permission::Read,
+			Insert(..) => Permission::Write,
+			Len(..) => Permission::Read,
+			Pop(..) => Permission::Delete,
+			Pos(..) => Permission::Read,
+			Push(..) => Permission::Write,
+			PushX(..) => Permission::Write,
+			Range(..) => Permission::Read,
+			Rem(..) => Permission::Delete,
+			Scan(..) => Permission::Read,
+			Set(..) => Permission::Write,
+			Trim(..) => Permission::Delete,
+		}
+	}
+}
+
 fn id() -> String {
 	Uuid::new_v4().to_string()
 }
@@ -62,58 +127,100 @@ fn new_index(index: isize, len: usize) -> usize {
 }
 
 pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
+	let span = tracing::info_span!(
+		"list",
+		command = cmd.name(),
+		key = cmd.key(),
+		records = field::Empty,
+		outcome = field::Empty,
+	);
+	node.start_command();
+
+	let res = run_list_cmd(node, cmd).instrument(span.clone()).await;
+
+	span.record("records", node.touched());
+	span.record("outcome", res.code().map_or("ok", |code| code.as_str()));
+	res
+}
+
+async fn run_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
+	if !node.permits(cmd.key(), cmd.permission()) {
+		let key = cmd.key().into();
+		return match cmd {
+			Collect(..) => ListResult::Collect(Err(LCollectError::PermissionDenied { key })),
+			Index(..) => ListResult::Index(Err(LIndexError::PermissionDenied { key })),
+			Insert(..) => ListResult::Insert(Err(LInsertError::PermissionDenied { key })),
+			Len(..) => ListResult::Len(Err(LLenError::PermissionDenied { key })),
+			Pop(..) => ListResult::Pop(Err(LPopError::PermissionDenied { key })),
+			Pos(..) => ListResult::Pos(Err(LPosError::PermissionDenied { key })),
+			Push(..) => ListResult::Push(Err(LPushError::PermissionDenied { key })),
+			PushX(..) => ListResult::PushX(Err(LPushError::PermissionDenied { key })),
+			Range(..) => ListResult::Range(Err(LRangeError::PermissionDenied { key })),
+			Rem(..) => ListResult::Rem(Err(LRemError::PermissionDenied { key })),
+			Scan(..) => ListResult::Scan(Err(LScanError::PermissionDenied { key })),
+			Set(..) => ListResult::Set(Err(LSetError::PermissionDenied { key })),
+			Trim(..) => ListResult::Trim(Err(LTrimError::PermissionDenied { key })),
+		};
+	}
+
 	match cmd {
 		Collect(key) => {
-			let items_key = format!("kl-items-{}", key);
-			let list = get_list!(node, items_key, ListResult, Collect, LCollectError);
+			let mut head = key_get!(ListIndex::load(node, key).await, key, ListResult, Collect, LCollectError);
+			let list = key_get!(head.all(node).await, key, ListResult, Collect, LCollectError);
 			let mut items = Vec::new();
 
-			for (index, id) in list.iter().enumerate() {
-				let item_key = format!("kl-{}", id);
-				match node.get(&item_key).await {
+			let record_keys: Vec<_> = list.iter().map(|id| keys::list_item(id)).collect();
+			let mut errors = Vec::new();
+
+			for (index, res) in node.get_many(&record_keys).await.into_iter().enumerate() {
+				match res {
 					Ok(data) => items.push(data),
-					Err(err) => return match err {
-						GetError::NotFound => ListResult::Collect(Err(LCollectError::NotFound {
+					Err(err) => errors.push(match err {
+						GetError::NotFound => LCollectError::NotFound {
 							key: key.into(),
 							index,
-						})),
-						GetError::QuorumFailed => ListResult::Collect(Err(LCollectError::QuorumFailed {
+						},
+						GetError::QuorumFailed => LCollectError::QuorumFailed {
 							key: key.into(),
 							index,
-						})),
-						GetError::Timeout => ListResult::Collect(Err(LCollectError::Timeout {
+						},
+						GetError::Timeout => LCollectError::Timeout {
 							key: key.into(),
 							index,
-						})),
-					},
+						},
+						GetError::Invalid => LCollectError::Invalid {
+							key: key.into(),
+							index,
+						},
+					}),
 				};
 			}
 
+			if !errors.is_empty() {
+				return ListResult::Collect(Err(LCollectError::Partial {
+					key: key.into(),
+					errors,
+				}));
+			}
+
 			ListResult::Collect(Ok(items))
 		},
 		Index(key, index) => {
-			let items_key = format!("kl-items-{}", key);
-			let list = get_list!(node, items_key, ListResult, Index, LIndexError);
+			let mut head = key_get!(ListIndex::load(node, key).await, key, ListResult, Index, LIndexError);
+			let len = head.len();
 
-			if out_of_bounds(index, list.len()) {
+			if out_of_bounds(index, len) {
 				return ListResult::Index(Err(LIndexError::OutOfBounds {
 					key: key.into(),
 					index,
-					len: list.len(),
+					len,
 				}));
 			}
 
-			let u_index = new_index(index, list.len());
+			let u_index = new_index(index, len);
 
-			let id = match list.get(u_index) {
-				Some(id) => id,
-				None => return ListResult::Index(Err(LIndexError::NotFound {
-					key: key.into(),
-					index,
-				})),
-			};
-
-			let item_key = format!("kl-{}", id);
+			let id = key_get!(head.get(node, u_index).await, key, ListResult, Index, LIndexError);
+			let item_key = keys::list_item(&id);
 			let item = match node.get(&item_key).await {
 				Ok(data) => data,
 				Err(err) => return match err {
@@ -129,31 +236,29 @@ pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
 						key: key.into(),
 						index,
 					})),
+					GetError::Invalid => ListResult::Index(Err(LIndexError::Invalid {
+						key: key.into(),
+						index,
+					})),
 				},
 			};
 
 			ListResult::Index(Ok(item))
 		},
 		Insert(key, index, item, after) => {
-			let items_key = format!("kl-items-{}", key);
-			let mut list = get_list!(node, items_key, ListResult, Insert, LInsertError);
+			let mut head = key_get!(ListIndex::load(node, key).await, key, ListResult, Insert, LInsertError);
+			let len = head.len();
 
-			let index = if after {
-				index + 1
-			} else {
-				index
-			};
-
-			if index.abs() as usize > list.len() {
+			if out_of_bounds(index, len) {
 				return ListResult::Insert(Err(LInsertError::OutOfBounds {
 					key: key.into(),
 					index,
-					len: list.len(),
+					len,
 				}))
 			}
 
 			let id = id();
-			let item_key = format!("kl-{}", id);
+			let item_key = keys::list_item(&id);
 
 			match node.put(&item_key, item).await {
 				Ok(_) => (),
@@ -166,41 +271,44 @@ pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
 						key: key.into(),
 						index,
 					})),
+					PutError::Rejected => ListResult::Insert(Err(LInsertError::Rejected {
+						key: key.into(),
+						index,
+					})),
 				}
 			}
 
-			let index = new_index(index, list.len());
-
-			list.insert(index, id);
+			// The new item goes before or after the one currently at `index`
+			let index = new_index(index, len) + after as usize;
 
-			join_list!(node, items_key, list, ListResult, Insert, LInsertError);
+			key_get!(head.insert(node, index, id).await, key, ListResult, Insert, LInsertError);
+			key_put!(head.save(node).await, key, ListResult, Insert, LInsertError);
 
 			ListResult::Insert(Ok(()))
 		},
 		Len(key) => {
-			let items_key = format!("kl-items-{}", key);
-			let list = get_list!(node, items_key, ListResult, Len, LLenError);
-			ListResult::Len(Ok(list.len()))
+			let head = key_get!(ListIndex::load(node, key).await, key, ListResult, Len, LLenError);
+			ListResult::Len(Ok(head.len()))
 		},
 		Pop(key, right) => {
-			let items_key = format!("kl-items-{}", key);
-			let mut list = get_list!(node, items_key, ListResult, Pop, LPopError);
+			let mut head = key_get!(ListIndex::load(node, key).await, key, ListResult, Pop, LPopError);
+			let len = head.len();
 
-			if list.len() == 0 {
+			if len == 0 {
 				return ListResult::Pop(Err(LPopError::EmptyList {
 					key: key.into(),
 				}))
 			}
 
 			let index = if right {
-				list.len() - 1
+				len - 1
 			} else {
 				0
 			};
 
-			let id = list.remove(index);
+			let id = key_get!(head.remove(node, index).await, key, ListResult, Pop, LPopError);
 
-			let item_key = format!("kl-{}", id);
+			let item_key = keys::list_item(&id);
 			let item = match node.get(&item_key).await {
 				Ok(data) => data,
 				Err(err) => return match err {
@@ -216,33 +324,42 @@ pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
 						key: key.into(),
 						index,
 					})),
+					GetError::Invalid => ListResult::Pop(Err(LPopError::Invalid {
+						key: key.into(),
+						index,
+					})),
 				},
 			};
 
-			node.remove(&item_key);
+			node.remove(&item_key).await;
 
-			join_list!(node, items_key, list, ListResult, Pop, LPopError);
+			key_put!(head.save(node).await, key, ListResult, Pop, LPopError);
 
 			ListResult::Pop(Ok(item))
 		},
 		Pos(key, test_item, rank) => {
-			let items_key = format!("kl-items-{}", key);
-			let list = get_list!(node, items_key, ListResult, Pos, LPosError);
+			let mut head = key_get!(ListIndex::load(node, key).await, key, ListResult, Pos, LPosError);
+			let list = key_get!(head.all(node).await, key, ListResult, Pos, LPosError);
 
-			let list = if rank > 0 {
-				list
-			} else if rank < 0 {
-				list.iter().rev().map(|s| s.into()).collect()
-			} else {
+			if rank == 0 {
 				return ListResult::Pos(Err(LPosError::RankZero {
 					key: key.into(),
 				}));
+			}
+
+			// A negative rank searches from the tail, but positions are always from the head
+			let len = list.len();
+			let positions: Box<dyn Iterator<Item = usize>> = if rank > 0 {
+				Box::new(0..len)
+			} else {
+				Box::new((0..len).rev())
 			};
 
 			let mut found = 0;
 
-			for (index, id) in list.iter().enumerate() {
-				let item_key = format!("kl-{}", id);
+			for index in positions {
+				let id = &list[index];
+				let item_key = keys::list_item(id);
 				let item = match node.get(&item_key).await {
 					Ok(data) => data,
 					Err(err) => return match err {
@@ -258,11 +375,15 @@ pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
 							key: key.into(),
 							index,
 						})),
+						GetError::Invalid => ListResult::Pos(Err(LPosError::Invalid {
+							key: key.into(),
+							index,
+						})),
 					},
 				};
 				if item == test_item {
 					found += 1;
-					if found == rank.abs() {
+					if found == rank.unsigned_abs() {
 						return ListResult::Pos(Ok(Some(index)));
 					}
 				}
@@ -271,11 +392,10 @@ pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
 			ListResult::Pos(Ok(None))
 		},
 		Push(key, item, right) => {
-			let items_key = format!("kl-items-{}", key);
-			let mut list = get_list_exists!(node, items_key, ListResult, Push, LPushError);
+			let mut head = key_get!(ListIndex::load_or_new(node, key).await, key, ListResult, Push, LPushError);
 
 			let id = id();
-			let item_key = format!("kl-{}", id);
+			let item_key = keys::list_item(&id);
 
 			match node.put(&item_key, item).await {
 				Ok(_) => (),
@@ -286,23 +406,26 @@ pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
 					PutError::Timeout => ListResult::Push(Err(LPushError::Timeout {
 						key: key.into(),
 					})),
+					PutError::Rejected => ListResult::Push(Err(LPushError::Rejected {
+						key: key.into(),
+					})),
 				}
 			}
 
-			if right {
-				list.push(id);
+			let index = if right {
+				head.len()
 			} else {
-				list.insert(0, id);
-			}
+				0
+			};
 
-			join_list!(node, items_key, list, ListResult, Push, LPushError);
+			key_get!(head.insert(node, index, id).await, key, ListResult, Push, LPushError);
+			key_put!(head.save(node).await, key, ListResult, Push, LPushError);
 
 			ListResult::Push(Ok(()))
 		},
 		PushX(key, item, right) => {
-			let items_key = format!("kl-items-{}", key);
-			let mut list = match node.get(&items_key).await {
-				Ok(list) => split_list(list),
+			let mut head = match ListIndex::load(node, key).await {
+				Ok(head) => head,
 				Err(err) => return match err {
 					GetError::NotFound => ListResult::PushX(Ok(())),
 					GetError::QuorumFailed => ListResult::PushX(Err(LPushError::KeyQuorumFailed {
@@ -311,11 +434,14 @@ pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
 					GetError::Timeout => ListResult::PushX(Err(LPushError::KeyTimeout {
 						key: key.into(),
 					})),
+					GetError::Invalid => ListResult::PushX(Err(LPushError::KeyInvalid {
+						key: key.into(),
+					})),
 				},
 			};
 
 			let id = id();
-			let item_key = format!("kl-{}", id);
+			let item_key = keys::list_item(&id);
 
 			match node.put(&item_key, item).await {
 				Ok(_) => (),
@@ -326,85 +452,107 @@ pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
 					PutError::Timeout => ListResult::PushX(Err(LPushError::Timeout {
 						key: key.into(),
 					})),
+					PutError::Rejected => ListResult::PushX(Err(LPushError::Rejected {
+						key: key.into(),
+					})),
 				}
 			}
 
-			if right {
-				list.push(id);
+			let index = if right {
+				head.len()
 			} else {
-				list.insert(0, id);
-			}
+				0
+			};
 
-			join_list!(node, items_key, list, ListResult, PushX, LPushError);
+			key_get!(head.insert(node, index, id).await, key, ListResult, PushX, LPushError);
+			key_put!(head.save(node).await, key, ListResult, PushX, LPushError);
 
 			ListResult::PushX(Ok(()))
 		},
 		Range(key, start, stop) => {
-			let items_key = format!("kl-items-{}", key);
-			let list = get_list!(node, items_key, ListResult, Range, LRangeError);
+			let mut head = key_get!(ListIndex::load(node, key).await, key, ListResult, Range, LRangeError);
+			let len = head.len();
 			let mut items = Vec::new();
 
-			if out_of_bounds(start, list.len()) {
+			if out_of_bounds(start, len) {
 				return ListResult::Range(Err(LRangeError::OutOfBounds {
 					key: key.into(),
 					index: start,
-					len: list.len(),
+					len,
 				}));
 			}
 
-			if out_of_bounds(stop, list.len()) {
+			if out_of_bounds(stop, len) {
 				return ListResult::Range(Err(LRangeError::OutOfBounds {
 					key: key.into(),
 					index: stop,
-					len: list.len(),
+					len,
 				}));
 			}
 
-			let start = new_index(start, list.len());
-			let stop = new_index(stop, list.len());
+			let start = new_index(start, len);
+			let stop = new_index(stop, len);
+
+			if start > stop {
+				return ListResult::Range(Ok((start, items)));
+			}
+
+			let list = key_get!(head.range(node, start, stop).await, key, ListResult, Range, LRangeError);
 
-			let list = &list[start..=stop];
+			let record_keys: Vec<_> = list.iter().map(|id| keys::list_item(id)).collect();
+			let mut errors = Vec::new();
 
-			for (index, id) in list.iter().enumerate() {
-				let item_key = format!("kl-{}", id);
-				match node.get(&item_key).await {
+			for (offset, res) in node.get_many(&record_keys).await.into_iter().enumerate() {
+				let index = start + offset;
+				match res {
 					Ok(data) => items.push(data),
-					Err(err) => return match err {
-						GetError::NotFound => ListResult::Range(Err(LRangeError::NotFound {
+					Err(err) => errors.push(match err {
+						GetError::NotFound => LRangeError::NotFound {
 							key: key.into(),
 							index,
-						})),
-						GetError::QuorumFailed => ListResult::Range(Err(LRangeError::QuorumFailed {
+						},
+						GetError::QuorumFailed => LRangeError::QuorumFailed {
 							key: key.into(),
 							index,
-						})),
-						GetError::Timeout => ListResult::Range(Err(LRangeError::Timeout {
+						},
+						GetError::Timeout => LRangeError::Timeout {
 							key: key.into(),
 							index,
-						})),
-					},
+						},
+						GetError::Invalid => LRangeError::Invalid {
+							key: key.into(),
+							index,
+						},
+					}),
 				};
 			}
 
-			ListResult::Range(Ok(items))
+			if !errors.is_empty() {
+				return ListResult::Range(Err(LRangeError::Partial {
+					key: key.into(),
+					errors,
+				}));
+			}
+
+			ListResult::Range(Ok((start, items)))
 		},
 		Rem(key, index) => {
-			let items_key = format!("kl-items-{}", key);
-			let mut list = get_list!(node, items_key, ListResult, Rem, LRemError);
+			let mut head = key_get!(ListIndex::load(node, key).await, key, ListResult, Rem, LRemError);
+			let len = head.len();
 
-			if out_of_bounds(index, list.len()) {
+			if out_of_bounds(index, len) {
 				return ListResult::Rem(Err(LRemError::OutOfBounds {
 					key: key.into(),
 					index,
-					len: list.len(),
+					len,
 				}))
 			}
 
-			let u_index = new_index(index, list.len());
+			let u_index = new_index(index, len);
 
-			let id = list.remove(u_index);
+			let id = key_get!(head.remove(node, u_index).await, key, ListResult, Rem, LRemError);
 
-			let item_key = format!("kl-{}", id);
+			let item_key = keys::list_item(&id);
 			let item = match node.get(&item_key).await {
 				Ok(data) => data,
 				Err(err) => return match err {
@@ -420,31 +568,89 @@ pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
 						key: key.into(),
 						index,
 					})),
+					GetError::Invalid => ListResult::Rem(Err(LRemError::Invalid {
+						key: key.into(),
+						index,
+					})),
 				},
 			};
 
-			node.remove(&item_key);
+			node.remove(&item_key).await;
 
-			join_list!(node, items_key, list, ListResult, Rem, LRemError);
+			key_put!(head.save(node).await, key, ListResult, Rem, LRemError);
 
 			ListResult::Rem(Ok(item))
 		},
+		Scan(key, cursor, count) => {
+			let mut head = key_get!(ListIndex::load(node, key).await, key, ListResult, Scan, LScanError);
+			let len = head.len();
+
+			let start = cursor.min(len);
+			let end = start.saturating_add(count.max(1)).min(len);
+
+			// Like Redis, a cursor of 0 means the whole list has been read
+			let next = if end < len { end } else { 0 };
+
+			if start == end {
+				return ListResult::Scan(Ok((next, Vec::new())));
+			}
+
+			let list = key_get!(head.range(node, start, end - 1).await, key, ListResult, Scan, LScanError);
+			let mut items = Vec::new();
+
+			let record_keys: Vec<_> = list.iter().map(|id| keys::list_item(id)).collect();
+			let mut errors = Vec::new();
+
+			for (offset, res) in node.get_many(&record_keys).await.into_iter().enumerate() {
+				let index = start + offset;
+				match res {
+					Ok(data) => items.push(data),
+					Err(err) => errors.push(match err {
+						GetError::NotFound => LScanError::NotFound {
+							key: key.into(),
+							index,
+						},
+						GetError::QuorumFailed => LScanError::QuorumFailed {
+							key: key.into(),
+							index,
+						},
+						GetError::Timeout => LScanError::Timeout {
+							key: key.into(),
+							index,
+						},
+						GetError::Invalid => LScanError::Invalid {
+							key: key.into(),
+							index,
+						},
+					}),
+				};
+			}
+
+			if !errors.is_empty() {
+				return ListResult::Scan(Err(LScanError::Partial {
+					key: key.into(),
+					errors,
+				}));
+			}
+
+			ListResult::Scan(Ok((next, items)))
+		},
 		Set(key, index, item) => {
-			let items_key = format!("kl-items-{}", key);
-			let list = get_list!(node, items_key, ListResult, Set, LSetError);
+			let mut head = key_get!(ListIndex::load(node, key).await, key, ListResult, Set, LSetError);
+			let len = head.len();
 
-			if out_of_bounds(index, list.len()) {
+			if out_of_bounds(index, len) {
 				return ListResult::Set(Err(LSetError::OutOfBounds {
 					key: key.into(),
 					index,
-					len: list.len(),
+					len,
 				}))
 			}
 
-			let u_index = new_index(index, list.len());
+			let u_index = new_index(index, len);
 
-			let id = &list[u_index];
-			let item_key = format!("kl-{}", id);
+			let id = key_get!(head.get(node, u_index).await, key, ListResult, Set, LSetError);
+			let item_key = keys::list_item(&id);
 
 			match node.put(&item_key, item).await {
 				Ok(_) => (),
@@ -457,51 +663,45 @@ pub async fn handle_list_cmd(node: &mut Node, cmd: ListCmd<'_>) -> ListResult {
 						key: key.into(),
 						index,
 					})),
+					PutError::Rejected => ListResult::Set(Err(LSetError::Rejected {
+						key: key.into(),
+						index,
+					})),
 				}
 			}
 
 			ListResult::Set(Ok(()))
 		},
 		Trim(key, start, stop) => {
-			let items_key = format!("kl-items-{}", key);
-			let list = get_list!(node, items_key, ListResult, Trim, LTrimError);
+			let mut head = key_get!(ListIndex::load(node, key).await, key, ListResult, Trim, LTrimError);
+			let len = head.len();
 
-			if out_of_bounds(start, list.len()) {
+			if out_of_bounds(start, len) {
 				return ListResult::Trim(Err(LTrimError::OutOfBounds {
 					key: key.into(),
 					index: start,
-					len: list.len(),
+					len,
 				}))
 			}
 
-			if out_of_bounds(stop, list.len()) {
+			if out_of_bounds(stop, len) {
 				return ListResult::Trim(Err(LTrimError::OutOfBounds {
 					key: key.into(),
 					index: stop,
-					len: list.len(),
+					len,
 				}))
 			}
 
-			let start = new_index(start, list.len());
-			let stop = new_index(stop, list.len());
-
-			if start > 0 {
-				for id in &list[0..start] {
-					let item_key = format!("kl-{}", id);
-					node.remove(&item_key);
-				}
-			}
+			let start = new_index(start, len);
+			let stop = new_index(stop, len);
 
-			if stop < list.len() - 1 {
-				for id in &list[stop + 1..list.len()] {
-					let item_key = format!("kl-{}", id);
-					node.remove(&item_key);
-				}
+			let dropped = key_get!(head.trim(node, start, stop).await, key, ListResult, Trim, LTrimError);
+			for id in &dropped {
+				let item_key = keys::list_item(id);
+				node.remove(&item_key).await;
 			}
 
-			let list = &list[start..=stop];
-
-			join_list!(node, items_key, list, ListResult, Trim, LTrimError);
+			key_put!(head.save(node).await, key, ListResult, Trim, LTrimError);
 
 			ListResult::Trim(Ok(()))
 		},
@@ -15,9 +15,11 @@
 //
 
 use async_std::task;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
-use crate::KadisBuilder;
+use super::index::SEGMENT_SIZE;
+use crate::{ErrorCode, Kadis, KadisBuilder, LRangeError};
 
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 struct Cat {
@@ -27,7 +29,7 @@ struct Cat {
 
 #[test]
 fn list() {
-	let mut kadis = KadisBuilder::default().bootstraps(&["/ip4/0.0.0.0/tcp/5130"]).init().unwrap();
+	let mut kadis = KadisBuilder::local().init().unwrap();
 
 	task::block_on(async move {
 		let cat = Cat {
@@ -135,5 +137,308 @@ fn list() {
 		let res = kadis.llen("cats").await;
 		assert!(res.is_ok());
 		assert_eq!(res.unwrap(), 1);
+
+		let mut cats = kadis.list::<Cat>("cats");
+		let cat = Cat {
+			name: "Ferb".into(),
+			color: "black".into(),
+		};
+		let res = cats.rpush(&cat).await;
+		assert!(res.is_ok());
+
+		let res = cats.range(0, -1).await;
+		assert!(res.is_ok());
+		assert_eq!(res.unwrap().last(), Some(&cat));
+	});
+}
+
+// Reference model of a list, `None` until the first push
+struct Model(Option<Vec<u32>>);
+
+impl Model {
+	fn list(&mut self) -> Result<&mut Vec<u32>, ErrorCode> {
+		self.0.as_mut().ok_or(ErrorCode::NotFound)
+	}
+
+	fn index(&mut self, index: isize) -> Result<usize, ErrorCode> {
+		let len = self.list()?.len() as isize;
+		if index >= len || index < -len {
+			return Err(ErrorCode::OutOfBounds);
+		}
+		Ok(if index < 0 { len + index } else { index } as usize)
+	}
+}
+
+async fn step(kadis: &mut Kadis, model: &mut Model, rng: &mut StdRng, key: &str) {
+	let len = model.0.as_ref().map_or(0, Vec::len) as isize;
+	let index = rng.gen_range(-len - 2, len + 2);
+	let stop = rng.gen_range(-len - 2, len + 2);
+	let item = rng.gen_range(0, 4u32);
+
+	match rng.gen_range(0, 12) {
+		0 => {
+			let right = rng.gen();
+			let res = if right {
+				kadis.rpush(key, item).await
+			} else {
+				kadis.lpush(key, item).await
+			};
+			assert!(res.is_ok());
+			let list = model.0.get_or_insert_with(Vec::new);
+			if right {
+				list.push(item);
+			} else {
+				list.insert(0, item);
+			}
+		},
+		1 => {
+			let right = rng.gen();
+			let res = if right {
+				kadis.rpush_exists(key, item).await
+			} else {
+				kadis.lpush_exists(key, item).await
+			};
+			assert!(res.is_ok());
+			if let Some(list) = model.0.as_mut() {
+				if right {
+					list.push(item);
+				} else {
+					list.insert(0, item);
+				}
+			}
+		},
+		2 => {
+			let right = rng.gen();
+			let res = if right {
+				kadis.rpop::<u32>(key).await
+			} else {
+				kadis.lpop::<u32>(key).await
+			};
+			let expected = model.list().and_then(|list| match list.len() {
+				0 => Err(ErrorCode::EmptyList),
+				len if right => Ok(list.remove(len - 1)),
+				_ => Ok(list.remove(0)),
+			});
+			assert_eq!(res.map_err(|err| err.code()), expected, "pop right={}", right);
+		},
+		3 => {
+			let res = kadis.lindex::<u32>(key, index).await;
+			let expected = model.index(index).map(|i| model.0.as_ref().unwrap()[i]);
+			assert_eq!(res.map_err(|err| err.code()), expected, "lindex {}", index);
+		},
+		4 => {
+			let after = rng.gen();
+			let res = if after {
+				kadis.linsert_after(key, index, item).await
+			} else {
+				kadis.linsert_before(key, index, item).await
+			};
+			let expected = model.index(index).map(|i| {
+				model.0.as_mut().unwrap().insert(i + after as usize, item);
+			});
+			assert_eq!(res.map_err(|err| err.code()), expected, "linsert {} after={}", index, after);
+		},
+		5 => {
+			let res = kadis.llen(key).await;
+			let expected = model.list().map(|list| list.len());
+			assert_eq!(res.map_err(|err| err.code()), expected, "llen");
+		},
+		6 => {
+			let rank = rng.gen_range(-3, 4);
+			let res = kadis.lpos_rank(key, item, rank).await;
+			let expected = model.list().and_then(|list| {
+				let mut positions: Vec<_> = (0..list.len()).filter(|&i| list[i] == item).collect();
+				if rank < 0 {
+					positions.reverse();
+				}
+				match rank {
+					0 => Err(ErrorCode::RankZero),
+					rank => Ok(positions.get(rank.unsigned_abs() as usize - 1).copied()),
+				}
+			});
+			assert_eq!(res.map_err(|err| err.code()), expected, "lpos {} rank={}", item, rank);
+		},
+		7 => {
+			let res = kadis.lrange::<u32>(key, index, stop).await;
+			let expected = model.index(index).and_then(|start| {
+				let stop = model.index(stop)?;
+				let list = model.list()?;
+				Ok(if start > stop { Vec::new() } else { list[start..=stop].to_vec() })
+			});
+			assert_eq!(res.map_err(|err| err.code()), expected, "lrange {} {}", index, stop);
+		},
+		8 => {
+			let res = kadis.lrem::<u32>(key, index).await;
+			let expected = model.index(index).map(|i| model.0.as_mut().unwrap().remove(i));
+			assert_eq!(res.map_err(|err| err.code()), expected, "lrem {}", index);
+		},
+		9 => {
+			let res = kadis.lset(key, index, item).await;
+			let expected = model.index(index).map(|i| model.0.as_mut().unwrap()[i] = item);
+			assert_eq!(res.map_err(|err| err.code()), expected, "lset {}", index);
+		},
+		10 => {
+			let res = kadis.ltrim::<u32>(key, index, stop).await;
+			let expected = model.index(index).and_then(|start| {
+				let stop = model.index(stop)?;
+				let list = model.list()?;
+				*list = if start > stop { Vec::new() } else { list[start..=stop].to_vec() };
+				Ok(())
+			});
+			assert_eq!(res.map_err(|err| err.code()), expected, "ltrim {} {}", index, stop);
+		},
+		_ => {
+			let res = kadis.lcollect::<u32>(key).await;
+			let expected = model.list().map(|list| list.clone());
+			assert_eq!(res.map_err(|err| err.code()), expected, "lcollect");
+		},
+	}
+}
+
+#[test]
+fn model() {
+	let mut kadis = KadisBuilder::local().init().unwrap();
+
+	task::block_on(async move {
+		for seed in 0..20 {
+			let key = format!("model-{}", seed);
+			let mut rng = StdRng::seed_from_u64(seed);
+			let mut model = Model(None);
+
+			for _ in 0..100 {
+				step(&mut kadis, &mut model, &mut rng, &key).await;
+			}
+
+			let res = kadis.lcollect::<u32>(&key).await;
+			assert_eq!(res.map_err(|err| err.code()), model.list().map(|list| list.clone()));
+		}
+	});
+}
+
+#[test]
+fn legacy() {
+	let mut kadis = KadisBuilder::local().init().unwrap();
+
+	// 0x4d and 0x53 are what manifests and sealed records start with
+	kadis.node.put_legacy("kl-a", &bincode::serialize(&0x4du32).unwrap());
+	kadis.node.put_legacy("kl-b", &bincode::serialize(&0x53u32).unwrap());
+	kadis.node.put_legacy("kl-items-nums", b"a,b");
+
+	task::block_on(async move {
+		let res = kadis.lrange::<u32>("nums", 0, -1).await;
+		assert_eq!(res.unwrap(), vec![0x4d, 0x53]);
+
+		let res = kadis.lindex::<u32>("nums", -1).await;
+		assert_eq!(res.unwrap(), 0x53);
+	});
+}
+
+#[test]
+fn range_errors() {
+	let mut kadis = KadisBuilder::local().init().unwrap();
+
+	// The item at index 2 is missing
+	kadis.node.put_legacy("kl-a", &bincode::serialize(&1u32).unwrap());
+	kadis.node.put_legacy("kl-b", &bincode::serialize(&2u32).unwrap());
+	kadis.node.put_legacy("kl-d", &bincode::serialize(&4u32).unwrap());
+	kadis.node.put_legacy("kl-items-nums", b"a,b,c,d");
+
+	task::block_on(async move {
+		let res = kadis.lrange::<u32>("nums", 1, 3).await;
+		match res {
+			Err(LRangeError::Partial { errors, .. }) => match errors.as_slice() {
+				[LRangeError::NotFound { index, .. }] => assert_eq!(*index, 2),
+				errors => panic!("unexpected errors {:?}", errors),
+			},
+			res => panic!("unexpected result {:?}", res),
+		}
+
+		// Too short to decode as a string
+		assert!(kadis.rpush("names", 7u32).await.is_ok());
+		for name in &["a", "b", "c"] {
+			assert!(kadis.rpush("names", name.to_string()).await.is_ok());
+		}
+		let res = kadis.lrange::<String>("names", -4, -1).await;
+		match res {
+			Err(LRangeError::Decode { index, .. }) => assert_eq!(index, 0),
+			res => panic!("unexpected result {:?}", res),
+		}
+		assert!(kadis.rpush("names", 7u32).await.is_ok());
+		let res = kadis.lrange::<String>("names", 2, -1).await;
+		match res {
+			Err(LRangeError::Decode { index, .. }) => assert_eq!(index, 4),
+			res => panic!("unexpected result {:?}", res),
+		}
+	});
+}
+
+#[test]
+fn segments() {
+	let mut kadis = KadisBuilder::local().init().unwrap();
+
+	task::block_on(async move {
+		// Pushed from both ends, so the list spans several segments
+		let mut model: Vec<u32> = Vec::new();
+		let half = SEGMENT_SIZE as u32 + 50;
+		for i in 0..half {
+			assert!(kadis.rpush("big", i).await.is_ok());
+			model.push(i);
+			assert!(kadis.lpush("big", half + i).await.is_ok());
+			model.insert(0, half + i);
+		}
+		assert!(model.len() > SEGMENT_SIZE * 2);
+
+		let len = model.len() as isize;
+		let at = |index: isize| if index < 0 { (len + index) as usize } else { index as usize };
+		for index in &[0, 255, 256, 257, 511, 512, len - 1, -1, -257] {
+			let res = kadis.lindex::<u32>("big", *index).await;
+			assert_eq!(res.unwrap(), model[at(*index)], "lindex {}", index);
+		}
+		for (start, stop) in &[(250, 520), (-300, -1), (0, len - 1)] {
+			let res = kadis.lrange::<u32>("big", *start, *stop).await;
+			assert_eq!(res.unwrap(), model[at(*start)..=at(*stop)].to_vec(), "lrange {} {}", start, stop);
+		}
+
+		assert!(kadis.linsert_before("big", 256, 9999u32).await.is_ok());
+		model.insert(256, 9999);
+		assert!(kadis.linsert_after("big", 511, 8888u32).await.is_ok());
+		model.insert(512, 8888);
+		let res = kadis.lrem::<u32>("big", 257).await;
+		assert_eq!(res.unwrap(), model.remove(257));
+		let res = kadis.lcollect::<u32>("big").await;
+		assert_eq!(res.unwrap(), model);
+
+		// Trimming drops whole segments along with their items
+		assert!(kadis.ltrim::<u32>("big", 300, 310).await.is_ok());
+		model = model[300..=310].to_vec();
+		let res = kadis.lcollect::<u32>("big").await;
+		assert_eq!(res.unwrap(), model);
+		// The items, the head and at most two segments
+		assert!(kadis.node.stored_records() <= model.len() + 3);
+	});
+}
+
+#[test]
+fn constant_records() {
+	let mut kadis = KadisBuilder::local().init().unwrap();
+
+	task::block_on(async move {
+		for i in 0..2u32 {
+			assert!(kadis.rpush("small", i).await.is_ok());
+		}
+		for i in 0..SEGMENT_SIZE as u32 * 3 + 10 {
+			assert!(kadis.rpush("big", i).await.is_ok());
+		}
+
+		// Pushing and popping touch the same records however long the list is
+		assert!(kadis.rpush("small", 2u32).await.is_ok());
+		let small = kadis.node.touched();
+		assert!(kadis.rpush("big", 2u32).await.is_ok());
+		assert_eq!(kadis.node.touched(), small);
+
+		assert!(kadis.lpop::<u32>("small").await.is_ok());
+		let small = kadis.node.touched();
+		assert!(kadis.lpop::<u32>("big").await.is_ok());
+		assert_eq!(kadis.node.touched(), small);
 	});
 }
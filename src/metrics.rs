@@ -0,0 +1,265 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+
+use std::{
+	io::{self, BufRead, BufReader, Read, Write},
+	net::{SocketAddr, TcpListener, TcpStream},
+	sync::mpsc,
+	thread,
+	time::Duration,
+};
+
+use prometheus::{
+	Encoder,
+	HistogramOpts,
+	HistogramVec,
+	IntCounter,
+	IntCounterVec,
+	IntGauge,
+	Opts,
+	Registry,
+	TextEncoder,
+};
+
+use crate::cache::CacheStats;
+use crate::node::{GetError, Node, PutError};
+use crate::ErrorCode;
+
+/// Statistics of one node, shared by all of its clones.
+pub struct Metrics {
+	registry: Registry,
+	commands: IntCounterVec,
+	command_seconds: HistogramVec,
+	errors: IntCounterVec,
+	query_seconds: HistogramVec,
+	cache_hits: IntCounter,
+	cache_misses: IntCounter,
+	records: IntGauge,
+	peers: IntGauge,
+}
+
+impl Metrics {
+	pub fn new() -> Self {
+		// Names and labels are fixed and the registry starts empty, so none of this can fail
+		let commands = IntCounterVec::new(
+			Opts::new("kadis_commands_total", "Commands run, by command"),
+			&["command"],
+		).unwrap();
+		let command_seconds = HistogramVec::new(
+			HistogramOpts::new("kadis_command_duration_seconds", "Time taken to run a command, by command"),
+			&["command"],
+		).unwrap();
+		let errors = IntCounterVec::new(
+			Opts::new("kadis_errors_total", "Commands that failed, by command and error code"),
+			&["command", "code"],
+		).unwrap();
+		let query_seconds = HistogramVec::new(
+			HistogramOpts::new("kadis_query_duration_seconds", "Time taken by DHT queries, by kind and outcome"),
+			&["kind", "outcome"],
+		).unwrap();
+		let cache_hits = IntCounter::new("kadis_cache_hits_total", "Reads answered by the cache").unwrap();
+		let cache_misses = IntCounter::new("kadis_cache_misses_total", "Reads the cache could not answer").unwrap();
+		let records = IntGauge::new("kadis_records", "Records held in the local record store").unwrap();
+		let peers = IntGauge::new("kadis_peers", "Peers currently connected").unwrap();
+
+		let registry = Registry::new();
+		registry.register(Box::new(commands.clone())).unwrap();
+		registry.register(Box::new(command_seconds.clone())).unwrap();
+		registry.register(Box::new(errors.clone())).unwrap();
+		registry.register(Box::new(query_seconds.clone())).unwrap();
+		registry.register(Box::new(cache_hits.clone())).unwrap();
+		registry.register(Box::new(cache_misses.clone())).unwrap();
+		registry.register(Box::new(records.clone())).unwrap();
+		registry.register(Box::new(peers.clone())).unwrap();
+
+		Self {
+			registry,
+			commands,
+			command_seconds,
+			errors,
+			query_seconds,
+			cache_hits,
+			cache_misses,
+			records,
+			peers,
+		}
+	}
+
+	pub fn command(&self, name: &str, elapsed: Duration, code: Option<ErrorCode>) {
+		self.commands.with_label_values(&[name]).inc();
+		self.command_seconds.with_label_values(&[name]).observe(elapsed.as_secs_f64());
+		if let Some(code) = code {
+			self.errors.with_label_values(&[name, code.as_str()]).inc();
+		}
+	}
+
+	pub fn get_query(&self, elapsed: Duration, res: &Result<Vec<u8>, GetError>) {
+		let outcome = res.as_ref().err().map_or("ok", GetError::as_str);
+		self.query_seconds.with_label_values(&["get", outcome]).observe(elapsed.as_secs_f64());
+	}
+
+	pub fn put_query(&self, elapsed: Duration, res: &Result<(), PutError>) {
+		let outcome = res.as_ref().err().map_or("ok", PutError::as_str);
+		self.query_seconds.with_label_values(&["put", outcome]).observe(elapsed.as_secs_f64());
+	}
+
+	/// Renders every metric, taking the values the node keeps elsewhere as they are now.
+	pub fn render(&self, cache: CacheStats, records: usize, peers: usize) -> String {
+		self.cache_hits.inc_by(cache.hits.saturating_sub(self.cache_hits.get()));
+		self.cache_misses.inc_by(cache.misses.saturating_sub(self.cache_misses.get()));
+		self.records.set(records as i64);
+		self.peers.set(peers as i64);
+
+		let mut buffer = Vec::new();
+		// Encoding into memory only fails for invalid metric families, which are never registered
+		let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+		String::from_utf8_lossy(&buffer).into_owned()
+	}
+}
+
+// Requests are tiny, so anything longer or slower is not a metrics scraper. Requests
+// are answered one at a time, so this also bounds how long a stalled client holds up
+// the others.
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Listener for `GET /metrics`, bound before the node it reports on is
+/// started so that a taken address fails node startup without leaving
+/// anything running.
+pub struct Server {
+	address: SocketAddr,
+	node: mpsc::Sender<Node>,
+}
+
+/// Binds `address` and starts the thread answering on it, which waits for
+/// [`Server::start`] before accepting connections.
+pub fn bind(address: &str) -> io::Result<Server> {
+	let listener = TcpListener::bind(address)?;
+	let address = listener.local_addr()?;
+	let (sender, receiver) = mpsc::channel();
+
+	thread::Builder::new()
+		.name("kadis-metrics".into())
+		.spawn(move || {
+			// Dropped without a node when startup fails
+			let node: Node = match receiver.recv() {
+				Ok(node) => node,
+				Err(_) => return,
+			};
+			for stream in listener.incoming() {
+				let stream = match stream {
+					Ok(stream) => stream,
+					Err(err) => {
+						log::debug!("Could not accept metrics request: {}", err);
+						continue;
+					},
+				};
+				if let Err(err) = respond(stream, &node) {
+					log::debug!("Could not answer metrics request: {}", err);
+				}
+			}
+		})?;
+
+	Ok(Server {
+		address,
+		node: sender,
+	})
+}
+
+impl Server {
+	/// The address actually bound.
+	pub fn address(&self) -> SocketAddr {
+		self.address
+	}
+
+	/// Starts answering requests with the metrics of `node`.
+	pub fn start(self, node: Node) {
+		// Only fails if the serving thread is gone, which leaves nothing to start
+		let _ = self.node.send(node);
+	}
+}
+
+fn respond(mut stream: TcpStream, node: &Node) -> io::Result<()> {
+	stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+	stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+	let mut reader = BufReader::new(stream.try_clone()?.take(MAX_REQUEST_SIZE));
+	let mut request = String::new();
+	reader.read_line(&mut request)?;
+
+	// The rest of the request is ignored, but read so the client sees a clean close
+	let mut line = String::new();
+	while reader.read_line(&mut line)? > 2 {
+		line.clear();
+	}
+
+	let mut parts = request.split_whitespace();
+	let (status, body) = match (parts.next(), parts.next()) {
+		(Some("GET"), Some("/metrics")) => ("200 OK", node.render_metrics()),
+		_ => ("404 Not Found", String::new()),
+	};
+	write!(
+		stream,
+		"HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+		status,
+		body.len(),
+		body,
+	)?;
+	stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+	use async_std::task;
+
+	use super::*;
+	use crate::KadisBuilder;
+
+	#[test]
+	fn metrics() {
+		let mut kadis = KadisBuilder::local().init().unwrap();
+
+		task::block_on(async move {
+			assert!(kadis.hset("cats", "herb", 1u32).await.is_ok());
+			assert!(kadis.hget::<u32>("cats", "herb").await.is_ok());
+			assert!(kadis.hget::<u32>("cats", "ferb").await.is_err());
+			assert!(kadis.lpop::<u32>("dogs").await.is_err());
+
+			let metrics = kadis.metrics();
+			assert!(metrics.contains("kadis_commands_total{command=\"hget\"} 2"));
+			assert!(metrics.contains("kadis_commands_total{command=\"hset\"} 1"));
+			assert!(metrics.contains("kadis_errors_total{code=\"NOTFOUND\",command=\"hget\"} 1"));
+			assert!(metrics.contains("kadis_errors_total{code=\"NOTFOUND\",command=\"lpop\"} 1"));
+			assert!(metrics.contains("kadis_records "));
+
+			let server = bind("127.0.0.1:0").unwrap();
+			let address = server.address();
+			server.start(kadis.node.clone());
+
+			let get = |path: &str| {
+				let mut stream = TcpStream::connect(address).unwrap();
+				write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+				let mut response = String::new();
+				stream.read_to_string(&mut response).unwrap();
+				response
+			};
+			assert!(get("/").starts_with("HTTP/1.1 404 Not Found"));
+			let response = get("/metrics");
+			assert!(response.starts_with("HTTP/1.1 200 OK"));
+			assert!(response.contains("kadis_commands_total{command=\"hset\"} 1"));
+		});
+	}
+}
@@ -15,23 +15,30 @@
 //
 
 use std::{
-    sync::{Arc, Mutex},
-    task::{Context, Poll},
+    collections::HashSet,
+    convert::TryInto,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
     thread,
     time::Duration,
 };
 
-use async_std::task;
 use fnv::FnvHashMap;
-use futures::prelude::*;
-use libp2p::kad::record::store::MemoryStore;
+use futures::{executor, prelude::*};
+use libp2p::floodsub::{Floodsub, FloodsubEvent, Topic};
+use libp2p::kad::record::store::RecordStore;
 use libp2p::kad::{
     GetRecordError,
     GetRecordOk,
     Kademlia,
+    KademliaConfig,
     KademliaEvent,
     PutRecordError,
     PutRecordOk,
+    QueryId,
     QueryResult,
     Quorum,
     Record,
@@ -46,24 +53,96 @@ use libp2p::{
     mdns::{Mdns, MdnsEvent},
     mplex::MplexConfig,
     noise::{self, NoiseConfig, X25519Spec},
-    swarm::{NetworkBehaviourEventProcess},
-    tcp::TcpConfig,
+    swarm::{toggle::Toggle, NetworkBehaviourEventProcess},
     yamux::YamuxConfig,
     PeerId, Swarm, Transport, NetworkBehaviour,
     identity,
 };
+use tracing::{field, Instrument};
 
-#[derive(Clone)]
+#[cfg(not(feature = "tokio"))]
+use libp2p::tcp::TcpConfig;
+#[cfg(feature = "tokio")]
+use libp2p::tcp::TokioTcpConfig as TcpConfig;
+
+use crate::acl::{self, Acl, AclTable, Permission};
+use crate::cache::{Cache, CacheConfig, CacheStats};
+use crate::chunk::{self, Manifest, CHUNK_SIZE};
+use crate::codec;
+use crate::compression::{self, CompressionConfig};
+use crate::crypto::{self, KeySet, Keyring};
+use crate::impl_error;
+use crate::keys::{self, RecordKey};
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, Metrics};
+use crate::runtime::{DefaultRuntime, Runtime};
+use crate::signature::{self, Validator};
+use crate::store::ValidatingStore;
+
+// Peers announce changed keys on this topic so others can drop them from their caches
+const INVALIDATE_TOPIC: &str = "kadis-invalidate";
+const ACL_TOPIC: &str = "kadis-acl";
+// How often the swarm is polled even when nothing happens
+const TICK: Duration = Duration::from_millis(100);
+const QUORUM: Quorum = Quorum::One;
+
+#[derive(Clone, Debug)]
 pub enum GetError {
     NotFound,
     QuorumFailed,
     Timeout,
+    Invalid,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum PutError {
     QuorumFailed,
     Timeout,
+    Rejected,
+}
+
+// Outcomes as reported by tracing and metrics
+impl GetError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GetError::NotFound => "not_found",
+            GetError::QuorumFailed => "quorum_failed",
+            GetError::Timeout => "timeout",
+            GetError::Invalid => "invalid",
+        }
+    }
+}
+
+impl PutError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PutError::QuorumFailed => "quorum_failed",
+            PutError::Timeout => "timeout",
+            PutError::Rejected => "rejected",
+        }
+    }
+}
+
+pub struct NodeConfig<'a> {
+    pub bootstraps: &'a [&'a str],
+    pub port: u16,
+    pub cache: CacheConfig,
+    pub compression: CompressionConfig,
+    pub keyring: Keyring,
+    pub identity: identity::Keypair,
+    pub validator: Validator,
+    pub acl_admins: Vec<PeerId>,
+    pub namespace: &'a str,
+    pub legacy_keys: bool,
+    pub parallelism: usize,
+    pub local: bool,
+    pub memory: bool,
+    pub query_timeout: Duration,
+    #[cfg(feature = "metrics")]
+    pub metrics_address: Option<&'a str>,
+    /// Faults injected into the memory transport by test clusters.
+    #[cfg(any(test, feature = "testing"))]
+    pub faults: Option<crate::testing::Faults>,
 }
 
 #[derive(Clone)]
@@ -83,112 +162,366 @@ pub enum NodeInitError {
     DialAddr {
         address: String,
     },
+    Listen {
+        address: String,
+    },
+    Transport {
+        reason: String,
+    },
+    Mdns {
+        reason: String,
+    },
+    Runtime {
+        reason: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum FlushError {
+    QuorumFailed {
+        namespace: String,
+    },
+    Timeout {
+        namespace: String,
+    },
+    Rejected {
+        namespace: String,
+    },
 }
 
+impl_error!(FlushError => Flush {
+    QuorumFailed { namespace } => QuorumFailed, "quorum failed while flushing namespace `{namespace}`",
+    Timeout { namespace } => Timeout, "request timed out while flushing namespace `{namespace}`",
+    Rejected { namespace } => Rejected, "generation of namespace `{namespace}` rejected by local store",
+});
+
+#[derive(Debug)]
+pub enum ScanError {
+    QuorumFailed {
+        namespace: String,
+    },
+    Timeout {
+        namespace: String,
+    },
+    Invalid {
+        namespace: String,
+    },
+}
+
+impl_error!(ScanError => Scan {
+    QuorumFailed { namespace } => QuorumFailed, "quorum failed while looking up namespace `{namespace}`",
+    Timeout { namespace } => Timeout, "request timed out while looking up namespace `{namespace}`",
+    Invalid { namespace } => Invalid, "invalid generation record for namespace `{namespace}`",
+});
+
+impl_error!(NodeInitError => NodeInit {
+    ParseAddress { address } => InvalidAddress, "could not parse listen address `{address}`",
+    ParseBootstrap { address } => InvalidAddress, "could not parse bootstrap address `{address}`",
+    DialAddr { address } => DialFailed, "could not dial bootstrap address `{address}`",
+    Listen { address } => Transport, "could not listen on `{address}`",
+    Transport { reason } => Transport, "could not set up transport: {reason}",
+    Mdns { reason } => Transport, "could not start mDNS: {reason}",
+    Runtime { reason } => Transport, "could not start the swarm task: {reason}",
+});
+
 #[derive(NetworkBehaviour)]
 struct Behaviour {
-    kademlia: Kademlia<MemoryStore>,
-    mdns: Mdns,
+    kademlia: Kademlia<ValidatingStore>,
+    mdns: Toggle<Mdns>,
+    floodsub: Floodsub,
     #[behaviour(ignore)]
-    event_results: FnvHashMap<String, EventResult>,
+    event_results: FnvHashMap<QueryId, EventResult>,
+    // Woken once the query finishes
+    #[behaviour(ignore)]
+    pending_queries: FnvHashMap<QueryId, Option<Waker>>,
+    #[behaviour(ignore)]
+    cache: Arc<Mutex<Cache>>,
+    #[behaviour(ignore)]
+    acls: Arc<Mutex<AclTable>>,
+    // Swarm task, woken when work is queued from outside it
+    #[behaviour(ignore)]
+    task: Option<Waker>,
+}
+
+impl Behaviour {
+    fn wake_task(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.wake();
+        }
+    }
+
+    fn announce_change(&mut self, key: &str) {
+        self.floodsub.publish(Topic::new(INVALIDATE_TOPIC), key.as_bytes());
+        self.wake_task();
+    }
+
+    // Sent as the ACL key, a newline and the signed record
+    fn announce_acl(&mut self, key: &str, record: &[u8]) {
+        let mut data = key.as_bytes().to_vec();
+        data.push(b'\n');
+        data.extend_from_slice(record);
+        self.floodsub.publish(Topic::new(ACL_TOPIC), data);
+        self.wake_task();
+    }
 }
 
 impl NetworkBehaviourEventProcess<MdnsEvent> for Behaviour {
     fn inject_event(&mut self, event: MdnsEvent) {
         if let MdnsEvent::Discovered(list) = event {
             for (peer_id, multiaddr) in list {
+                self.floodsub.add_node_to_partial_view(peer_id.clone());
                 self.kademlia.add_address(&peer_id, multiaddr);
             }
         }
     }
 }
 
+impl NetworkBehaviourEventProcess<FloodsubEvent> for Behaviour {
+    fn inject_event(&mut self, event: FloodsubEvent) {
+        if let FloodsubEvent::Message(message) = event {
+            if message.topics.contains(&Topic::new(ACL_TOPIC)) {
+                // Messages without a separator cannot be ACLs
+                let split = match message.data.iter().position(|b| *b == b'\n') {
+                    Some(split) => split,
+                    None => return,
+                };
+                let (key, record) = message.data.split_at(split);
+                let key = String::from_utf8_lossy(key);
+                let mut acls = self.acls.lock().unwrap();
+                match acls.validate(&key, &record[1..]) {
+                    Ok((prefix, acl)) => acls.insert(prefix, acl),
+                    Err(reason) => log::warn!("Ignoring announced ACL {}: {}", key, reason),
+                }
+            } else if let Ok(key) = std::str::from_utf8(&message.data) {
+                self.cache.lock().unwrap().invalidate(key);
+            }
+        }
+    }
+}
+
 impl NetworkBehaviourEventProcess<KademliaEvent> for Behaviour {
     fn inject_event(&mut self, message: KademliaEvent) {
         match message {
-            KademliaEvent::QueryResult { result, .. } => match result {
-                QueryResult::GetRecord(Ok(GetRecordOk { records, .. })) => {
-                    let record = &records.last().unwrap().record;
-                    let key = std::str::from_utf8(record.key.as_ref()).unwrap();
-                    let name = format!("get-{}", key);
-                    let value = record.value.clone();
-                    self.event_results.insert(name, EventResult::Get(Ok(value)));
-                },
-                QueryResult::GetRecord(Err(err)) => {
-                    let (key, err) = match err {
-                        GetRecordError::NotFound { key, .. } => (key, GetError::NotFound),
-                        GetRecordError::QuorumFailed { key, .. } => (key, GetError::QuorumFailed),
-                        GetRecordError::Timeout { key, .. } => (key, GetError::Timeout),
-                    };
-                    let key = std::str::from_utf8(key.as_ref()).unwrap();
-                    let name = format!("get-{}", key);
-                    self.event_results.insert(name, EventResult::Get(Err(err)));
-                },
-                QueryResult::PutRecord(Ok(PutRecordOk { key })) => {
-                    let key = std::str::from_utf8(key.as_ref()).unwrap();
-                    let name = format!("put-{}", key);
-                    self.event_results.insert(name, EventResult::Put(Ok(())));
-                },
-                QueryResult::PutRecord(Err(err)) => {
-                    let (key, err) = match err {
-                        PutRecordError::QuorumFailed { key, .. } => (key, PutError::QuorumFailed),
-                        PutRecordError::Timeout { key, .. } => (key, PutError::Timeout),
-                    };
-                    let key = std::str::from_utf8(key.as_ref()).unwrap();
-                    let name = format!("put-{}", key);
-                    self.event_results.insert(name, EventResult::Put(Err(err)));
-                },
-                _ => (),
+            KademliaEvent::QueryResult { id, result, .. } => {
+                let waker = match self.pending_queries.remove(&id) {
+                    Some(waker) => waker,
+                    None => return,
+                };
+                let res = match result {
+                    QueryResult::GetRecord(Ok(GetRecordOk { records, .. })) => match records.last() {
+                        Some(peer_record) => EventResult::Get(Ok(peer_record.record.value.clone())),
+                        None => EventResult::Get(Err(GetError::NotFound)),
+                    },
+                    QueryResult::GetRecord(Err(err)) => EventResult::Get(Err(match err {
+                        GetRecordError::NotFound { .. } => GetError::NotFound,
+                        GetRecordError::QuorumFailed { .. } => GetError::QuorumFailed,
+                        GetRecordError::Timeout { .. } => GetError::Timeout,
+                    })),
+                    QueryResult::PutRecord(Ok(PutRecordOk { .. })) => EventResult::Put(Ok(())),
+                    QueryResult::PutRecord(Err(err)) => EventResult::Put(Err(match err {
+                        PutRecordError::QuorumFailed { .. } => PutError::QuorumFailed,
+                        PutRecordError::Timeout { .. } => PutError::Timeout,
+                    })),
+                    _ => return,
+                };
+                self.event_results.insert(id, res);
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            },
+            KademliaEvent::RoutingUpdated { peer, .. } => {
+                self.floodsub.add_node_to_partial_view(peer);
             },
             _ => (),
         }
     }
 }
 
+#[derive(Clone)]
+enum Backend {
+    Swarm(Arc<Mutex<Swarm<Behaviour>>>),
+    // Local nodes keep records in their own store and have no peers to ask
+    Local(Arc<Mutex<ValidatingStore>>),
+}
+
+#[derive(Clone)]
 pub struct Node {
-    swarm: Arc<Mutex<Swarm<Behaviour>>>,
-    cache: Arc<Mutex<FnvHashMap<String, Vec<u8>>>>,
+    backend: Backend,
+    cache: Arc<Mutex<Cache>>,
+    acls: Arc<Mutex<AclTable>>,
+    read_through: bool,
+    compression: CompressionConfig,
+    keyring: Keyring,
+    sealer: Option<Arc<KeySet>>,
+    local_key: identity::Keypair,
+    local_peer_id: PeerId,
+    validator: Validator,
+    scope: String,
+    namespace: String,
+    generation: Option<u64>,
+    legacy_keys: bool,
+    parallelism: usize,
+    // Records read, written or removed by the current command
+    touched: Arc<AtomicUsize>,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Metrics>,
+}
+
+// Flushing a namespace moves it to a new generation, leaving the old records
+// unreachable until they expire. Keys in generation 0 of the default namespace
+// are left as they are.
+fn generation_key(namespace: &str) -> String {
+    format!("kg-{}", namespace)
+}
+
+fn put_error(err: GetError) -> PutError {
+    match err {
+        GetError::QuorumFailed => PutError::QuorumFailed,
+        GetError::Timeout => PutError::Timeout,
+        GetError::NotFound | GetError::Invalid => PutError::Rejected,
+    }
 }
 
 impl Node {
-	pub fn new(bootstraps: &[&str], port: u16, cache_lifetime: u64) -> Result<Self, NodeInitError> {
-	    let local_key = identity::Keypair::generate_ed25519();
+	pub fn new(config: NodeConfig<'_>) -> Result<Self, NodeInitError> {
+	    let local_peer_id = PeerId::from(config.identity.public());
+        let cache = Arc::new(Mutex::new(Cache::new(config.cache)));
+        let acls = Arc::new(Mutex::new(AclTable::new(&config.acl_admins)));
+
+        // Bound first, so a taken address fails before the swarm is running
+        #[cfg(feature = "metrics")]
+        let metrics_server = match config.metrics_address {
+            Some(address) => match metrics::bind(address) {
+                Ok(server) => Some(server),
+                Err(_) => return Err(NodeInitError::Listen { address: address.into() }),
+            },
+            None => None,
+        };
+
+        let backend = if config.local {
+            let store = ValidatingStore::new(local_peer_id.clone(), config.validator.clone(), acls.clone());
+            Backend::Local(Arc::new(Mutex::new(store)))
+        } else {
+            Backend::Swarm(Self::start_swarm(&config, &cache, &acls)?)
+        };
+
+        let NodeConfig { cache: cache_config, compression, keyring, identity: local_key, validator, namespace, legacy_keys, parallelism, .. } = config;
+
+	    let node = Self {
+            backend,
+            cache,
+            acls,
+            read_through: cache_config.read_through,
+            compression,
+            keyring,
+            sealer: None,
+            local_key,
+            local_peer_id,
+            validator,
+            scope: String::new(),
+            namespace: namespace.into(),
+            generation: None,
+            legacy_keys,
+            parallelism: parallelism.max(1),
+            touched: Arc::new(AtomicUsize::new(0)),
+            #[cfg(feature = "metrics")]
+            metrics: Arc::new(Metrics::new()),
+	    };
+
+        #[cfg(feature = "metrics")]
+        if let Some(server) = metrics_server {
+            log::info!("Serving metrics on {}", server.address());
+            server.start(node.clone());
+        }
+
+        Ok(node)
+	}
+
+    fn start_swarm(
+        config: &NodeConfig<'_>,
+        cache: &Arc<Mutex<Cache>>,
+        acls: &Arc<Mutex<AclTable>>,
+    ) -> Result<Arc<Mutex<Swarm<Behaviour>>>, NodeInitError> {
+        let NodeConfig { bootstraps, port, memory, identity: local_key, validator, .. } = config;
 	    let local_peer_id = PeerId::from(local_key.public());
 
-	    let transport = {
-		    let dh_keys = noise::Keypair::<X25519Spec>::new().into_authentic(&local_key).unwrap();
-		    let noise = NoiseConfig::xx(dh_keys).into_authenticated();
-		    let tcp = TcpConfig::new();
+	    let dh_keys = match noise::Keypair::<X25519Spec>::new().into_authentic(local_key) {
+            Ok(dh_keys) => dh_keys,
+            Err(err) => return Err(NodeInitError::Transport { reason: err.to_string() }),
+        };
+	    let noise = NoiseConfig::xx(dh_keys).into_authenticated();
+        let multiplex = SelectUpgrade::new(YamuxConfig::default(), MplexConfig::new());
+
+        // Memory transports connect nodes within this process only
+	    let transport = if *memory {
+            #[cfg(any(test, feature = "testing"))]
+            let memory = crate::testing::faults::transport(config.faults.clone(), (*port).into());
+            #[cfg(not(any(test, feature = "testing")))]
+            let memory = libp2p::core::transport::MemoryTransport;
 
-	    	DnsConfig::new(tcp)
-                .unwrap()
+            memory
 		        .upgrade(Version::V1)
 		        .authenticate(noise)
-		        .multiplex(SelectUpgrade::new(
-		            YamuxConfig::default(),
-		            MplexConfig::new(),
-		        ))
+		        .multiplex(multiplex)
+		        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+		        .boxed()
+        } else {
+            let dns = match DnsConfig::new(TcpConfig::new()) {
+                Ok(dns) => dns,
+                Err(err) => return Err(NodeInitError::Transport { reason: err.to_string() }),
+            };
+	    	dns
+		        .upgrade(Version::V1)
+		        .authenticate(noise)
+		        .multiplex(multiplex)
 		        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
 		        .boxed()
 	    };
 
 	    let mut swarm = {
-	    	let store = MemoryStore::new(local_peer_id.clone());
+	    	let store = ValidatingStore::new(local_peer_id.clone(), validator.clone(), acls.clone());
             let event_results = FnvHashMap::default();
-		    let kademlia = Kademlia::new(local_peer_id.clone(), store);
-		    let mdns = task::block_on(Mdns::new()).unwrap();
-		    let behaviour = Behaviour { event_results, kademlia, mdns };
-		    Swarm::new(transport, behaviour, local_peer_id)
+            let pending_queries = FnvHashMap::default();
+		    let mut kademlia_config = KademliaConfig::default();
+		    kademlia_config.set_query_timeout(config.query_timeout);
+		    let kademlia = Kademlia::with_config(local_peer_id.clone(), store, kademlia_config);
+		    // mDNS runs on its own reactor, so any executor can wait for it
+		    let mdns = match *memory {
+                true => None,
+                false => match executor::block_on(Mdns::new()) {
+                    Ok(mdns) => Some(mdns),
+                    Err(err) => return Err(NodeInitError::Mdns { reason: err.to_string() }),
+                },
+            };
+		    let mut floodsub = Floodsub::new(local_peer_id.clone());
+		    floodsub.subscribe(Topic::new(INVALIDATE_TOPIC));
+		    floodsub.subscribe(Topic::new(ACL_TOPIC));
+		    let behaviour = Behaviour {
+                event_results,
+                pending_queries,
+                kademlia,
+                mdns: mdns.into(),
+                floodsub,
+                cache: cache.clone(),
+                acls: acls.clone(),
+                task: None,
+            };
+		    Swarm::new(transport, behaviour, local_peer_id.clone())
 		};
 
-        let address = format!("/ip4/0.0.0.0/tcp/{}", port);
-        let address = match address.parse() {
+        let address = match *memory {
+            true => format!("/memory/{}", port),
+            false => format!("/ip4/0.0.0.0/tcp/{}", port),
+        };
+        let listen_address = match address.parse() {
             Ok(address) => address,
             Err(_) => return Err(NodeInitError::ParseAddress { address }),
         };
-	    Swarm::listen_on(&mut swarm, address).unwrap();
+        if Swarm::listen_on(&mut swarm, listen_address).is_err() {
+            return Err(NodeInitError::Listen { address });
+        }
 
-        for address in bootstraps {
+        for address in bootstraps.iter() {
             let dial_address = match address.parse() {
                 Ok(address) => address,
                 Err(_) => return Err(NodeInitError::ParseBootstrap { address: address.to_string() }),
@@ -200,16 +533,20 @@ impl Node {
         }
 
         let swarm = Arc::new(Mutex::new(swarm));
-        let cache = Arc::new(Mutex::new(FnvHashMap::default()));
 
 	    let mut listening = false;
+        let mut tick = None;
         let swarm_clone = swarm.clone();
-	    task::spawn(future::poll_fn(move |cx: &mut Context<'_>| -> Poll<Result<(), ()>> {
+	    let spawned = DefaultRuntime::spawn(future::poll_fn(move |cx: &mut Context<'_>| -> Poll<()> {
+            // Kademlia only notices queries timing out when it is polled
+            while tick.get_or_insert_with(|| DefaultRuntime::sleep(TICK)).poll_unpin(cx).is_ready() {
+                tick = None;
+            }
 	        loop {
                 let mut swarm = swarm_clone.lock().unwrap();
 	            match swarm.poll_next_unpin(cx) {
 	                Poll::Ready(Some(event)) => log::info!("{:?}", event),
-	                Poll::Ready(None) => return Poll::Ready(Ok(())),
+	                Poll::Ready(None) => return Poll::Ready(()),
 	                Poll::Pending => {
 	                    if !listening {
 	                        if let Some(addr) = Swarm::listeners(&swarm).next() {
@@ -217,104 +554,684 @@ impl Node {
 	                            listening = true;
 	                        }
 	                    }
+                        swarm.task = Some(cx.waker().clone());
                         break;
 	                }
 	            }
 	        }
 	        Poll::Pending
 	    }));
-
-        // Clean cache on an interval of cache_lifetime
-        let cache_clone = cache.clone();
-        task::spawn(async move {
-            loop {
-                cache_clone.lock().unwrap().clear();
-                task::sleep(Duration::from_secs(cache_lifetime)).await;
-            }
-        });
+        if let Err(err) = spawned {
+            return Err(NodeInitError::Runtime { reason: err.to_string() });
+        }
 
         if !bootstraps.is_empty() {
             thread::sleep(Duration::from_millis(100));
         }
 
-	    Ok(Self {
-            swarm,
-            cache,
-	    })
+        Ok(swarm)
 	}
 
-    fn wait_for_result(&self, name: String) -> EventResult {
-        loop {
-            let event_results = &mut self.swarm.lock().unwrap().event_results;
-            match event_results.get(&name) {
-                Some(res) => {
-                    let res = res.clone();
-                    event_results.remove(&name);
-                    return res;
-                },
-                None => (),
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats()
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.local_peer_id.clone()
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn render_metrics(&self) -> String {
+        let (records, peers) = match &self.backend {
+            Backend::Swarm(swarm) => {
+                let mut swarm = swarm.lock().unwrap();
+                let peers = Swarm::network_info(&swarm).num_peers();
+                (swarm.kademlia.store_mut().records().count(), peers)
+            },
+            Backend::Local(store) => (store.lock().unwrap().records().count(), 0),
+        };
+        self.metrics.render(self.cache_stats(), records, peers)
+    }
+
+    /// Stores a record the way nodes did before records had headers.
+    #[cfg(test)]
+    pub fn put_legacy(&self, key: &str, value: &[u8]) {
+        if let Backend::Local(store) = &self.backend {
+            let record = Record {
+                key: Key::new(&key),
+                value: value.to_vec(),
+                publisher: None,
+                expires: None,
+            };
+            store.lock().unwrap().put(record).unwrap();
+        }
+    }
+
+    /// Starts counting the records touched from scratch. Clones made before
+    /// this keep counting for the command they were made for.
+    pub fn start_command(&mut self) {
+        self.touched = Arc::new(AtomicUsize::new(0));
+    }
+
+    pub fn touched(&self) -> usize {
+        self.touched.load(Ordering::Relaxed)
+    }
+
+    /// Sets the user key that following records belong to, which picks the
+    /// encryption keys and allowed writers used for them.
+    pub fn scope(&mut self, key: &str) {
+        self.scope = key.into();
+        self.sealer = self.keyring.find(key);
+        self.generation = None;
+    }
+
+    /// Adds a peer to the routing table and connects to it, as if it had
+    /// been discovered over mDNS.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn add_peer(&self, peer_id: &PeerId, address: libp2p::Multiaddr) {
+        if let Backend::Swarm(swarm) = &self.backend {
+            let swarm = &mut *swarm.lock().unwrap();
+            swarm.floodsub.add_node_to_partial_view(peer_id.clone());
+            swarm.kademlia.add_address(peer_id, address);
+            // Peers already in the partial view are not dialed again by floodsub
+            if !Swarm::is_connected(swarm, peer_id) {
+                if let Err(err) = Swarm::dial(swarm, peer_id) {
+                    log::debug!("Could not dial {}: {:?}", peer_id, err);
+                }
             }
+            swarm.wake_task();
         }
     }
 
-	pub async fn get(&mut self, key: &str) -> Result<Vec<u8>, GetError> {
-        {
-            let kademlia = &mut self.swarm.lock().unwrap().kademlia;
-            let key = Key::new(&key);
-            kademlia.get_record(&key, Quorum::One);
+    /// Number of peers in the routing table.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn known_peers(&self) -> usize {
+        match &self.backend {
+            Backend::Swarm(swarm) => swarm.lock().unwrap().kademlia.kbuckets().map(|bucket| bucket.num_entries()).sum(),
+            Backend::Local(_) => 0,
         }
+    }
 
-        if let Some(value) = self.cache.lock().unwrap().get(key) {
-            return Ok(value.clone());
+    /// Number of records in this node's own store.
+    #[cfg(test)]
+    pub fn stored_records(&self) -> usize {
+        match &self.backend {
+            Backend::Swarm(swarm) => swarm.lock().unwrap().kademlia.store_mut().records().count(),
+            Backend::Local(store) => store.lock().unwrap().records().count(),
         }
+    }
 
-        let name = format!("get-{}", key);
-        let res = self.wait_for_result(name);
-        match res {
-            EventResult::Get(res) => match res {
-                Ok(value) => {
-                    self.cache.lock().unwrap().insert(key.into(), value.clone());
-                    Ok(value)
+    /// Drops every record and cached value, as if the node had restarted.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn clear(&self) {
+        if let Backend::Swarm(swarm) = &self.backend {
+            let mut swarm = swarm.lock().unwrap();
+            let store = swarm.kademlia.store_mut();
+            let keys: Vec<Key> = store.records().map(|record| record.key.clone()).collect();
+            for key in keys {
+                store.remove(&key);
+            }
+        }
+        self.cache.lock().unwrap().clear();
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    pub fn is_connected(&self, peer_id: &PeerId) -> bool {
+        match &self.backend {
+            Backend::Swarm(swarm) => Swarm::is_connected(&swarm.lock().unwrap(), peer_id),
+            Backend::Local(_) => false,
+        }
+    }
+
+    pub fn parallelism(&self) -> usize {
+        self.parallelism
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Returns a node sharing this one's swarm that works in `namespace`.
+    pub fn with_namespace(&self, namespace: &str) -> Node {
+        Node {
+            namespace: namespace.into(),
+            generation: None,
+            ..self.clone()
+        }
+    }
+
+    async fn load_generation(&mut self) -> Result<u64, GetError> {
+        let key = generation_key(&self.namespace);
+        if let Some(value) = self.cache.lock().unwrap().get(&key) {
+            return Ok(u64::from_le_bytes(value.as_slice().try_into().unwrap_or_default()));
+        }
+
+        let generation = match self.fetch(&key).await {
+            Ok(record) => {
+                let record = self.verify(&key, record, Some(&self.namespace))?;
+                match record.as_slice().try_into() {
+                    Ok(bytes) => u64::from_le_bytes(bytes),
+                    Err(_) => {
+                        log::warn!("Invalid generation record {}", key);
+                        return Err(GetError::Invalid);
+                    },
+                }
+            },
+            Err(GetError::NotFound) => 0,
+            Err(err) => return Err(err),
+        };
+        self.cache.lock().unwrap().insert(&key, generation.to_le_bytes().to_vec());
+        Ok(generation)
+    }
+
+    /// Turns `key` into the record key used for it in the current namespace.
+    async fn namespaced(&mut self, key: &str) -> Result<String, GetError> {
+        let generation = match self.generation {
+            Some(generation) => generation,
+            None => {
+                let generation = self.load_generation().await?;
+                self.generation = Some(generation);
+                generation
+            },
+        };
+
+        if self.namespace.is_empty() && generation == 0 {
+            Ok(key.into())
+        } else {
+            Ok(format!("n{}:{}:{}:{}", self.namespace.len(), self.namespace, generation, key))
+        }
+    }
+
+    pub async fn flush(&mut self) -> Result<(), PutError> {
+        let key = generation_key(&self.namespace);
+        self.cache.lock().unwrap().invalidate(&key);
+        let generation = self.load_generation().await.map_err(put_error)? + 1;
+        let value = generation.to_le_bytes().to_vec();
+
+        // Belongs to the namespace itself, so its writers and ACLs decide who may flush it
+        let record = self.sign(&key, &self.namespace, &value)?;
+        self.store_signed(&key, record).await?;
+        self.announce_change(&key);
+        self.cache.lock().unwrap().insert(&key, value);
+        self.generation = None;
+        Ok(())
+    }
+
+    /// Sorted user keys of the hashes and lists in the current namespace that
+    /// this node stores the index of and may read. A DHT cannot list every key,
+    /// so other nodes may hold keys missing here.
+    pub async fn local_keys(&mut self) -> Result<Vec<String>, GetError> {
+        let prefix = self.namespaced("").await?;
+        let record_keys: Vec<String> = match &self.backend {
+            Backend::Swarm(swarm) => swarm.lock().unwrap().kademlia.store_mut().records()
+                .map(|record| String::from_utf8_lossy(record.key.as_ref()).into_owned())
+                .collect(),
+            Backend::Local(store) => store.lock().unwrap().records()
+                .map(|record| String::from_utf8_lossy(record.key.as_ref()).into_owned())
+                .collect(),
+        };
+
+        let mut user_keys: Vec<String> = record_keys.iter()
+            .filter_map(|key| key.strip_prefix(prefix.as_str()))
+            .filter_map(keys::index_owner)
+            .filter(|key| self.permits(key, Permission::Read))
+            .map(String::from)
+            .collect();
+        user_keys.sort();
+        user_keys.dedup();
+        Ok(user_keys)
+    }
+
+    pub fn permits(&self, key: &str, permission: Permission) -> bool {
+        self.acls.lock().unwrap().permits(key, Some(&self.local_peer_id), permission)
+    }
+
+    pub fn may_update_acl(&self, prefix: &str) -> bool {
+        self.acls.lock().unwrap().may_update(prefix, Some(&self.local_peer_id))
+    }
+
+    fn verify(&self, key: &str, record: Vec<u8>, scope: Option<&str>) -> Result<Vec<u8>, GetError> {
+        match self.validator.check(key, &record, scope, &self.acls.lock().unwrap()) {
+            Ok(payload) => Ok(payload.to_vec()),
+            Err(reason) => {
+                log::warn!("Rejecting record {}: {}", key, reason);
+                Err(GetError::Invalid)
+            },
+        }
+    }
+
+    fn announce_change(&self, key: &str) {
+        if let Backend::Swarm(swarm) = &self.backend {
+            swarm.lock().unwrap().announce_change(key);
+        }
+    }
+
+    async fn wait_for_result(swarm: &Mutex<Swarm<Behaviour>>, id: QueryId) -> EventResult {
+        future::poll_fn(|cx| {
+            let behaviour = &mut *swarm.lock().unwrap();
+            match behaviour.event_results.remove(&id) {
+                Some(res) => Poll::Ready(res),
+                None => {
+                    behaviour.pending_queries.insert(id, Some(cx.waker().clone()));
+                    Poll::Pending
                 },
-                Err(err) => Err(err),
+            }
+        }).await
+    }
+
+    async fn fetch(&mut self, key: &str) -> Result<Vec<u8>, GetError> {
+        let swarm = match &self.backend {
+            Backend::Swarm(swarm) => swarm,
+            Backend::Local(store) => {
+                return match store.lock().unwrap().get(&Key::new(&key)) {
+                    Some(record) => Ok(record.value.clone()),
+                    None => Err(GetError::NotFound),
+                };
             },
+        };
+        let id = {
+            let behaviour = &mut *swarm.lock().unwrap();
+            let id = behaviour.kademlia.get_record(&Key::new(&key), QUORUM);
+            behaviour.pending_queries.insert(id, None);
+            behaviour.wake_task();
+            id
+        };
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+
+        let span = tracing::debug_span!("query", kind = "get", id = ?id);
+        let res = match Self::wait_for_result(swarm, id).instrument(span).await {
+            EventResult::Get(res) => res,
             _ => unreachable!(),
+        };
+        #[cfg(feature = "metrics")]
+        self.metrics.get_query(started.elapsed(), &res);
+        res
+    }
+
+    fn sign(&self, key: &str, owner: &str, value: &[u8]) -> Result<Vec<u8>, PutError> {
+        match signature::sign(&self.local_key, key, owner, value) {
+            Ok(value) => Ok(value),
+            Err(reason) => {
+                log::warn!("Could not sign record {}: {}", key, reason);
+                Err(PutError::Rejected)
+            },
         }
-	}
+    }
 
-	pub async fn put(&mut self, key: &str, value: Vec<u8>) -> Result<(), PutError> {
-        {
-            let kademlia = &mut self.swarm.lock().unwrap().kademlia;
-            let key = Key::new(&key);
-            let record = Record {
-                key,
-                value: value.clone(),
-                publisher: None,
-                expires: None,
-            };
-            kademlia.put_record(record, Quorum::One).unwrap();
+    async fn store(&mut self, key: &str, value: Vec<u8>) -> Result<(), PutError> {
+        let value = self.sign(key, &self.scope, &value)?;
+        self.store_signed(key, value).await
+    }
+
+    async fn store_signed(&mut self, key: &str, value: Vec<u8>) -> Result<(), PutError> {
+        let record = Record {
+            key: Key::new(&key),
+            value,
+            publisher: Some(self.local_peer_id.clone()),
+            expires: None,
+        };
+        let swarm = match &self.backend {
+            Backend::Swarm(swarm) => swarm,
+            Backend::Local(store) => {
+                return store.lock().unwrap().put(record).map_err(|err| {
+                    log::warn!("Record {} rejected by local store: {:?}", key, err);
+                    PutError::Rejected
+                });
+            },
+        };
+        let id = {
+            let behaviour = &mut *swarm.lock().unwrap();
+            match behaviour.kademlia.put_record(record, QUORUM) {
+                Ok(id) => {
+                    behaviour.pending_queries.insert(id, None);
+                    behaviour.wake_task();
+                    id
+                },
+                Err(err) => {
+                    log::warn!("Record {} rejected by local store: {:?}", key, err);
+                    return Err(PutError::Rejected);
+                },
+            }
+        };
+
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+
+        let span = tracing::debug_span!("query", kind = "put", id = ?id);
+        let res = match Self::wait_for_result(swarm, id).instrument(span).await {
+            EventResult::Put(res) => res,
+            _ => unreachable!(),
+        };
+        #[cfg(feature = "metrics")]
+        self.metrics.put_query(started.elapsed(), &res);
+        res
+    }
+
+    /// Reads a record, falling back to its legacy key if enabled.
+    pub async fn get(&mut self, key: &RecordKey) -> Result<Vec<u8>, GetError> {
+        let span = tracing::debug_span!("get", key = key.as_str(), quorum = ?QUORUM, outcome = field::Empty);
+        self.touched.fetch_add(1, Ordering::Relaxed);
+
+        let res = async {
+            match (self.get_record(key.as_str()).await, key.legacy()) {
+                (Err(GetError::NotFound), Some(legacy)) if self.legacy_keys => {
+                    let value = self.get_legacy_record(legacy).await?;
+                    Ok(match key.holds_value() {
+                        true => codec::legacy(&value),
+                        false => value,
+                    })
+                },
+                (res, _) => res,
+            }
+        }.instrument(span.clone()).await;
+
+        span.record("outcome", res.as_ref().err().map_or("ok", GetError::as_str));
+        res
+    }
+
+    /// Reads several records with up to `parallelism` queries in flight,
+    /// keeping the result of each one.
+    pub async fn get_many(&mut self, keys: &[RecordKey]) -> Vec<Result<Vec<u8>, GetError>> {
+        // Looked up once here instead of by every read
+        if let Err(err) = self.namespaced("").await {
+            return keys.iter().map(|_| Err(err.clone())).collect();
         }
 
-        let name = format!("put-{}", key);
-        let res = self.wait_for_result(name);
-        match res {
-            EventResult::Put(res) => match res {
-                Ok(()) => {
-                    self.cache.lock().unwrap().insert(key.into(), value);
-                    Ok(())
+        let node = &*self;
+        stream::iter(keys)
+            .map(|key| {
+                let mut node = node.clone();
+                async move { node.get(key).await }
+            })
+            .buffered(self.parallelism)
+            .collect()
+            .await
+    }
+
+    /// Writes a record under its current key. Legacy records are left in place
+    /// until removed, so they stay readable until their data type rewrites them.
+    pub async fn put(&mut self, key: &RecordKey, value: Vec<u8>) -> Result<(), PutError> {
+        let span = tracing::debug_span!("put", key = key.as_str(), quorum = ?QUORUM, outcome = field::Empty);
+        self.touched.fetch_add(1, Ordering::Relaxed);
+
+        let res = self.put_record(key.as_str(), value).instrument(span.clone()).await;
+
+        span.record("outcome", res.as_ref().err().map_or("ok", PutError::as_str));
+        res
+    }
+
+    pub async fn remove(&mut self, key: &RecordKey) {
+        self.touched.fetch_add(1, Ordering::Relaxed);
+        self.remove_record(key.as_str()).await;
+        if let (Some(legacy), true) = (key.legacy(), self.legacy_keys) {
+            self.remove_record(legacy).await;
+        }
+    }
+
+	async fn get_record(&mut self, key: &str) -> Result<Vec<u8>, GetError> {
+        let key = self.namespaced(key).await?;
+        let key = key.as_str();
+
+        if self.read_through {
+            if let Some(value) = self.cache.lock().unwrap().get(key) {
+                return Ok(value);
+            }
+        }
+
+        let record = self.fetch(key).await?;
+        let mut record = self.verify(key, record, Some(&self.scope))?;
+
+        // Large values are stored as a manifest of chunks, possibly nested
+        while chunk::is_manifest(&record) {
+            let manifest = match Manifest::parse(&record) {
+                Ok(manifest) => manifest,
+                Err(reason) => {
+                    log::warn!("Invalid manifest for record {}: {}", key, reason);
+                    return Err(GetError::Invalid);
+                },
+            };
+
+            let node = &*self;
+            let fetched: Vec<_> = stream::iter(manifest.keys())
+                .map(|chunk_key| {
+                    let mut node = node.clone();
+                    async move {
+                        let res = node.fetch(&chunk_key).await;
+                        (chunk_key, res)
+                    }
+                })
+                .buffered(self.parallelism)
+                .collect()
+                .await;
+
+            let mut chunks = Vec::with_capacity(fetched.len());
+            for (chunk_key, res) in fetched {
+                match res {
+                    Ok(chunk) => chunks.push(self.verify(&chunk_key, chunk, None)?),
+                    Err(GetError::NotFound) => {
+                        log::warn!("Chunk {} of record {} is missing", chunk_key, key);
+                        return Err(GetError::Invalid);
+                    },
+                    Err(err) => return Err(err),
+                }
+            }
+
+            record = match manifest.assemble(key, chunks) {
+                Ok(record) => record,
+                Err(reason) => {
+                    log::warn!("Could not reassemble record {}: {}", key, reason);
+                    return Err(GetError::Invalid);
                 },
-                Err(err) => Err(err),
+            };
+        }
+
+        // Plaintext is refused where a key is configured, so writers cannot downgrade it
+        match (&self.sealer, crypto::is_sealed(&record)) {
+            (Some(keys), true) => {
+                record = match crypto::open(keys, key, &record) {
+                    Ok(record) => record,
+                    Err(reason) => {
+                        log::warn!("Could not decrypt record {}: {}", key, reason);
+                        return Err(GetError::Invalid);
+                    },
+                };
             },
-            _ => unreachable!(),
+            (Some(_), false) => {
+                log::warn!("Record {} is not encrypted but a key is configured for it", key);
+                return Err(GetError::Invalid);
+            },
+            (None, true) => {
+                log::warn!("Record {} is encrypted but no key is configured for it", key);
+                return Err(GetError::Invalid);
+            },
+            (None, false) => (),
+        }
+
+        let value = match compression::decompress(&record) {
+            Ok(value) => value,
+            Err(reason) => {
+                log::warn!("Could not decompress record {}: {}", key, reason);
+                return Err(GetError::Invalid);
+            },
+        };
+        self.cache.lock().unwrap().insert(key, value.clone());
+        Ok(value)
+	}
+
+	// Legacy records are stored as they were written, without headers or envelopes
+	async fn get_legacy_record(&mut self, key: &str) -> Result<Vec<u8>, GetError> {
+        let key = self.namespaced(key).await?;
+        let key = key.as_str();
+
+        if self.read_through {
+            if let Some(value) = self.cache.lock().unwrap().get(key) {
+                return Ok(value);
+            }
+        }
+
+        let value = self.fetch(key).await?;
+        let checked = match self.sealer {
+            Some(_) => Err("record is not encrypted but a key is configured for it".into()),
+            None => self.validator.check_signer(None, Some(&self.scope), &self.acls.lock().unwrap()),
+        };
+        if let Err(reason) = checked {
+            log::warn!("Rejecting legacy record {}: {}", key, reason);
+            return Err(GetError::Invalid);
+        }
+
+        self.cache.lock().unwrap().insert(key, value.clone());
+        Ok(value)
+	}
+
+	async fn put_record(&mut self, key: &str, value: Vec<u8>) -> Result<(), PutError> {
+        let key = self.namespaced(key).await.map_err(put_error)?;
+        let key = key.as_str();
+
+        let mut record = compression::compress(self.compression, &value);
+
+        // Sealed after compressing, since ciphertext does not compress
+        if let Some(keys) = &self.sealer {
+            record = match crypto::seal(keys, key, &record) {
+                Ok(record) => record,
+                Err(reason) => {
+                    log::warn!("Could not encrypt record {}: {}", key, reason);
+                    return Err(PutError::Rejected);
+                },
+            };
+        }
+
+        let stale = self.local_chunks(key);
+        let mut chunk_keys = HashSet::new();
+        while record.len() > CHUNK_SIZE {
+            let (manifest, chunks) = chunk::split(key, &record);
+            chunk_keys.extend(chunks.iter().map(|(chunk_key, _)| chunk_key.clone()));
+
+            let node = &*self;
+            stream::iter(chunks)
+                .map(|(chunk_key, chunk)| {
+                    let mut node = node.clone();
+                    async move { node.store(&chunk_key, chunk).await }
+                })
+                .buffer_unordered(self.parallelism)
+                .try_collect::<Vec<_>>()
+                .await?;
+            record = manifest;
         }
+
+        self.store(key, record).await?;
+        self.announce_change(key);
+        self.cache.lock().unwrap().insert(key, value);
+
+        // Chunks are salted with their record's key, so no other record uses them
+        for chunk_key in stale.iter().filter(|chunk_key| !chunk_keys.contains(*chunk_key)) {
+            self.remove_stored(chunk_key);
+        }
+        Ok(())
 	}
 
-    pub fn remove(&mut self, key: &str) {
-        {
-            let kademlia = &mut self.swarm.lock().unwrap().kademlia;
-            let key = Key::new(&key);
-            kademlia.remove_record(&key);
+    // A copy of the record stored under `key` in this node's own store
+    fn local_record(&self, key: &str) -> Option<Vec<u8>> {
+        let key = Key::new(&key);
+        match &self.backend {
+            Backend::Swarm(swarm) => swarm.lock().unwrap().kademlia.store_mut().get(&key).map(|record| record.value.clone()),
+            Backend::Local(store) => store.lock().unwrap().get(&key).map(|record| record.value.clone()),
+        }
+    }
+
+    // Keys of the chunks the record stored under `key` was split into, as far
+    // as this node holds them
+    fn local_chunks(&self, key: &str) -> Vec<String> {
+        let mut chunk_keys = Vec::new();
+        let mut record = match self.local_record(key) {
+            Some(record) => match signature::open(key, &record, false) {
+                Ok(signed) => signed.payload.to_vec(),
+                Err(_) => return chunk_keys,
+            },
+            None => return chunk_keys,
+        };
+
+        while chunk::is_manifest(&record) {
+            let manifest = match Manifest::parse(&record) {
+                Ok(manifest) => manifest,
+                Err(_) => break,
+            };
+            let keys = manifest.keys();
+            let chunks: Option<Vec<_>> = keys.iter()
+                .map(|chunk_key| {
+                    let chunk = self.local_record(chunk_key)?;
+                    signature::open(chunk_key, &chunk, false).ok().map(|signed| signed.payload.to_vec())
+                })
+                .collect();
+            chunk_keys.extend(keys);
+
+            record = match chunks.map(|chunks| manifest.assemble(key, chunks)) {
+                Some(Ok(record)) => record,
+                _ => break,
+            };
+        }
+        chunk_keys
+    }
+
+    /// Fetches the ACL for `prefix` and remembers it for later checks.
+    pub async fn get_acl(&mut self, prefix: &str) -> Result<Acl, GetError> {
+        let key = acl::acl_key(prefix);
+        let record = self.fetch(&key).await?;
+        let mut acls = self.acls.lock().unwrap();
+        match acls.validate(&key, &record) {
+            Ok((_, acl)) => {
+                acls.insert(prefix, acl.clone());
+                Ok(acl)
+            },
+            Err(reason) => {
+                log::warn!("Rejecting ACL {}: {}", key, reason);
+                Err(GetError::Invalid)
+            },
+        }
+    }
+
+    /// Stores the ACL for `prefix` and announces it to other nodes.
+    pub async fn put_acl(&mut self, prefix: &str, acl: &Acl) -> Result<(), PutError> {
+        let key = acl::acl_key(prefix);
+        let record = self.sign(&key, &key, &acl.encode())?;
+        self.store_signed(&key, record.clone()).await?;
+        self.acls.lock().unwrap().insert(prefix, acl.clone());
+        if let Backend::Swarm(swarm) = &self.backend {
+            swarm.lock().unwrap().announce_acl(&key, &record);
+        }
+        Ok(())
+    }
+
+    async fn remove_record(&mut self, key: &str) {
+        let key = match self.namespaced(key).await {
+            Ok(key) => key,
+            Err(_) => {
+                log::warn!("Could not look up the namespace of {} to remove it", key);
+                return;
+            },
+        };
+        let key = key.as_str();
+
+        for chunk_key in self.local_chunks(key) {
+            self.remove_stored(&chunk_key);
+        }
+        self.remove_stored(key);
+    }
+
+    fn remove_stored(&self, key: &str) {
+        match &self.backend {
+            Backend::Swarm(swarm) => {
+                let behaviour = &mut *swarm.lock().unwrap();
+                behaviour.kademlia.remove_record(&Key::new(&key));
+                behaviour.announce_change(key);
+            },
+            Backend::Local(store) => store.lock().unwrap().remove(&Key::new(&key)),
         }
-        self.cache.lock().unwrap().remove(key.into());
+        self.cache.lock().unwrap().invalidate(key);
     }
 }
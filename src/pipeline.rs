@@ -0,0 +1,458 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use futures::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::codec::{self, Codec, CodecError};
+use crate::hash::*;
+use crate::list::*;
+use crate::node::Node;
+use crate::scan::Scan;
+use crate::{handle_cmd, Cmd, CmdResult, KadisError};
+
+/// A value read by a pipeline, decoded on demand with the handle's codec.
+pub struct Value {
+	data: Vec<u8>,
+	codec: Arc<dyn Codec>,
+}
+
+impl Value {
+	pub fn decode<T>(&self) -> Result<T, CodecError>
+	where T: DeserializeOwned {
+		self.codec.decode(&self.data)
+	}
+
+	pub fn into_bytes(self) -> Result<Vec<u8>, CodecError> {
+		codec::payload(self.data)
+	}
+}
+
+impl fmt::Debug for Value {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Value").field("data", &self.data).finish()
+	}
+}
+
+/// Result of one pipelined command.
+#[derive(Debug)]
+pub enum Reply {
+	/// Returned by commands with nothing to return, such as `hset` and `rpush`.
+	Ok,
+	Bool(bool),
+	Len(usize),
+	Pos(Option<usize>),
+	Keys(Vec<String>),
+	Value(Value),
+	Values(Vec<Value>),
+	Map(HashMap<String, Value>),
+	/// A page of `hscan`, with the cursor of the next page.
+	Fields(usize, Vec<(String, Value)>),
+	/// A page of `lscan`, with the cursor of the next page.
+	Items(usize, Vec<Value>),
+}
+
+fn reply(codec: &Arc<dyn Codec>, res: CmdResult) -> Result<Reply, KadisError> {
+	let value = |data| Value {
+		data,
+		codec: codec.clone(),
+	};
+	let values = |data: Vec<Vec<u8>>| Reply::Values(data.into_iter().map(value).collect());
+
+	Ok(match res {
+		CmdResult::Hash(res) => match res {
+			HashResult::Del(res) => res.map(|_| Reply::Ok)?,
+			HashResult::Exists(res) => Reply::Bool(res?),
+			HashResult::Get(res) => Reply::Value(value(res?)),
+			HashResult::GetM(res) => values(res?),
+			HashResult::GetAll(res) => Reply::Map(res?.into_iter().map(|(field, data)| (field, value(data))).collect()),
+			HashResult::Incr(res) => res.map(|_| Reply::Ok)?,
+			HashResult::Keys(res) => Reply::Keys(res?),
+			HashResult::Len(res) => Reply::Len(res?),
+			HashResult::Scan(res) => {
+				let (cursor, page) = res?;
+				Reply::Fields(cursor, page.into_iter().map(|(field, data)| (field, value(data))).collect())
+			},
+			HashResult::Set(res) => res.map(|_| Reply::Ok)?,
+			HashResult::SetM(res) => res.map(|_| Reply::Ok)?,
+			HashResult::SetNx(res) => res.map(|_| Reply::Ok)?,
+			HashResult::Vals(res) => values(res?.into_iter().map(|(_, data)| data).collect()),
+		},
+		CmdResult::List(res) => match res {
+			ListResult::Collect(res) => values(res?),
+			ListResult::Index(res) => Reply::Value(value(res?)),
+			ListResult::Insert(res) => res.map(|_| Reply::Ok)?,
+			ListResult::Len(res) => Reply::Len(res?),
+			ListResult::Pop(res) => Reply::Value(value(res?)),
+			ListResult::Pos(res) => Reply::Pos(res?),
+			ListResult::Push(res) => res.map(|_| Reply::Ok)?,
+			ListResult::PushX(res) => res.map(|_| Reply::Ok)?,
+			ListResult::Range(res) => values(res?.1),
+			ListResult::Rem(res) => Reply::Value(value(res?)),
+			ListResult::Scan(res) => {
+				let (cursor, page) = res?;
+				Reply::Items(cursor, page.into_iter().map(value).collect())
+			},
+			ListResult::Set(res) => res.map(|_| Reply::Ok)?,
+			ListResult::Trim(res) => res.map(|_| Reply::Ok)?,
+		},
+	})
+}
+
+/// Commands queued to be sent together, created with `Kadis::pipeline`.
+///
+/// Nothing is atomic. Commands on the same key run one after another in the
+/// order they were queued, while commands on different keys run concurrently.
+pub struct Pipeline<'a> {
+	node: Node,
+	codec: Arc<dyn Codec>,
+	cmds: Vec<Result<Cmd<'a>, KadisError>>,
+}
+
+impl<'a> Pipeline<'a> {
+	pub(crate) fn new(node: Node, codec: Arc<dyn Codec>) -> Self {
+		Self {
+			node,
+			codec,
+			cmds: Vec::new(),
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.cmds.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.cmds.is_empty()
+	}
+
+	fn queue(&mut self, cmd: Cmd<'a>) -> &mut Self {
+		self.cmds.push(Ok(cmd));
+		self
+	}
+
+	// Values that cannot be encoded are reported in their command's place
+	fn fail<E>(&mut self, err: E) -> &mut Self
+	where E: Into<KadisError> {
+		self.cmds.push(Err(err.into()));
+		self
+	}
+
+	/// Runs every queued command, returning their results in order.
+	pub async fn exec(self) -> Vec<Result<Reply, KadisError>> {
+		let Pipeline { node, codec, cmds } = self;
+		let mut results = Vec::with_capacity(cmds.len());
+		let mut groups: Vec<Vec<(usize, Cmd<'a>)>> = Vec::new();
+		let mut by_key = HashMap::new();
+
+		for (position, cmd) in cmds.into_iter().enumerate() {
+			match cmd {
+				Ok(cmd) => {
+					let group = *by_key.entry(cmd.key().to_string()).or_insert_with(|| {
+						groups.push(Vec::new());
+						groups.len() - 1
+					});
+					groups[group].push((position, cmd));
+					results.push(None);
+				},
+				Err(err) => results.push(Some(Err(err))),
+			}
+		}
+
+		let parallelism = node.parallelism();
+		let done: Vec<Vec<(usize, CmdResult)>> = stream::iter(groups)
+			.map(|group| {
+				let mut node = node.clone();
+				async move {
+					let mut done = Vec::with_capacity(group.len());
+					for (position, cmd) in group {
+						done.push((position, handle_cmd(&mut node, cmd).await));
+					}
+					done
+				}
+			})
+			.buffer_unordered(parallelism)
+			.collect()
+			.await;
+
+		for (position, res) in done.into_iter().flatten() {
+			results[position] = Some(reply(&codec, res));
+		}
+		results.into_iter().flatten().collect()
+	}
+
+	pub fn hdel_multiple(&mut self, key: &'a str, fields: &'a [&'a str]) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::Del(key, fields)))
+	}
+
+	pub fn hexists(&mut self, key: &'a str, field: &'a str) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::Exists(key, field)))
+	}
+
+	pub fn hget(&mut self, key: &'a str, field: &'a str) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::Get(key, field)))
+	}
+
+	pub fn hget_multiple(&mut self, key: &'a str, fields: &'a [&'a str]) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::GetM(key, fields)))
+	}
+
+	pub fn hgetall(&mut self, key: &'a str) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::GetAll(key)))
+	}
+
+	pub fn hincr(&mut self, key: &'a str, field: &'a str, inc: u32) -> &mut Self {
+		self.hincr_float(key, field, inc as f32)
+	}
+
+	pub fn hincr_float(&mut self, key: &'a str, field: &'a str, inc: f32) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::Incr(key, field, inc)))
+	}
+
+	pub fn hkeys(&mut self, key: &'a str) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::Keys(key)))
+	}
+
+	pub fn hlen(&mut self, key: &'a str) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::Len(key)))
+	}
+
+	pub fn hscan(&mut self, key: &'a str, scan: &Scan<'a>) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::Scan(key, scan.cursor, scan.count, scan.pattern)))
+	}
+
+	pub fn hset<T>(&mut self, key: &'a str, field: &'a str, value: T) -> &mut Self
+	where T: Serialize {
+		match self.codec.encode(&value) {
+			Ok(value) => self.queue(Cmd::Hash(HashCmd::Set(key, field, value))),
+			Err(cause) => self.fail(HSetError::Encode {
+				key: key.into(),
+				field: field.into(),
+				cause: cause.into(),
+			}),
+		}
+	}
+
+	pub fn hset_bytes(&mut self, key: &'a str, field: &'a str, value: &[u8]) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::Set(key, field, codec::raw(value))))
+	}
+
+	pub fn hset_multiple<T>(&mut self, key: &'a str, fields: &'a [&'a str], values: &[T]) -> &mut Self
+	where T: Serialize {
+		// Checked before encoding, which would stop at the shorter of the two
+		if fields.len() != values.len() {
+			return self.fail(HSetError::LengthMismatch {
+				key: key.into(),
+				fields: fields.len(),
+				values: values.len(),
+			});
+		}
+		let values = values.iter()
+			.zip(fields)
+			.map(|(v, field)| self.codec.encode(v).map_err(|cause| HSetError::Encode {
+				key: key.into(),
+				field: (*field).into(),
+				cause: cause.into(),
+			}))
+			.collect::<Result<_, _>>();
+		match values {
+			Ok(values) => self.queue(Cmd::Hash(HashCmd::SetM(key, fields, values))),
+			Err(err) => self.fail(err),
+		}
+	}
+
+	pub fn hset_multiple_bytes(&mut self, key: &'a str, fields: &'a [&'a str], values: &[&[u8]]) -> &mut Self {
+		let values = values.iter().map(|v| codec::raw(v)).collect();
+		self.queue(Cmd::Hash(HashCmd::SetM(key, fields, values)))
+	}
+
+	pub fn hset_nx<T>(&mut self, key: &'a str, field: &'a str, value: T) -> &mut Self
+	where T: Serialize {
+		match self.codec.encode(&value) {
+			Ok(value) => self.queue(Cmd::Hash(HashCmd::SetNx(key, field, value))),
+			Err(cause) => self.fail(HSetError::Encode {
+				key: key.into(),
+				field: field.into(),
+				cause: cause.into(),
+			}),
+		}
+	}
+
+	pub fn hset_nx_bytes(&mut self, key: &'a str, field: &'a str, value: &[u8]) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::SetNx(key, field, codec::raw(value))))
+	}
+
+	pub fn hvals(&mut self, key: &'a str) -> &mut Self {
+		self.queue(Cmd::Hash(HashCmd::Vals(key)))
+	}
+
+	pub fn lcollect(&mut self, key: &'a str) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Collect(key)))
+	}
+
+	pub fn lindex(&mut self, key: &'a str, index: isize) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Index(key, index)))
+	}
+
+	fn linsert<T>(&mut self, key: &'a str, index: isize, item: T, after: bool) -> &mut Self
+	where T: Serialize {
+		match self.codec.encode(&item) {
+			Ok(item) => self.queue(Cmd::List(ListCmd::Insert(key, index, item, after))),
+			Err(cause) => self.fail(LInsertError::Encode {
+				key: key.into(),
+				index,
+				cause: cause.into(),
+			}),
+		}
+	}
+
+	pub fn linsert_before<T>(&mut self, key: &'a str, index: isize, item: T) -> &mut Self
+	where T: Serialize {
+		self.linsert(key, index, item, false)
+	}
+
+	pub fn linsert_after<T>(&mut self, key: &'a str, index: isize, item: T) -> &mut Self
+	where T: Serialize {
+		self.linsert(key, index, item, true)
+	}
+
+	pub fn linsert_before_bytes(&mut self, key: &'a str, index: isize, item: &[u8]) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Insert(key, index, codec::raw(item), false)))
+	}
+
+	pub fn linsert_after_bytes(&mut self, key: &'a str, index: isize, item: &[u8]) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Insert(key, index, codec::raw(item), true)))
+	}
+
+	pub fn llen(&mut self, key: &'a str) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Len(key)))
+	}
+
+	pub fn lpop(&mut self, key: &'a str) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Pop(key, false)))
+	}
+
+	pub fn rpop(&mut self, key: &'a str) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Pop(key, true)))
+	}
+
+	pub fn lpos_rank<T>(&mut self, key: &'a str, item: T, rank: i32) -> &mut Self
+	where T: Serialize {
+		match self.codec.encode(&item) {
+			Ok(item) => self.queue(Cmd::List(ListCmd::Pos(key, item, rank))),
+			Err(cause) => self.fail(LPosError::Encode {
+				key: key.into(),
+				cause: cause.into(),
+			}),
+		}
+	}
+
+	pub fn lpos<T>(&mut self, key: &'a str, item: T) -> &mut Self
+	where T: Serialize {
+		self.lpos_rank(key, item, 1)
+	}
+
+	pub fn lpos_rank_bytes(&mut self, key: &'a str, item: &[u8], rank: i32) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Pos(key, codec::raw(item), rank)))
+	}
+
+	pub fn lpos_bytes(&mut self, key: &'a str, item: &[u8]) -> &mut Self {
+		self.lpos_rank_bytes(key, item, 1)
+	}
+
+	fn lrpush<T>(&mut self, key: &'a str, item: T, right: bool, exists: bool) -> &mut Self
+	where T: Serialize {
+		match self.codec.encode(&item) {
+			Ok(item) if exists => self.queue(Cmd::List(ListCmd::PushX(key, item, right))),
+			Ok(item) => self.queue(Cmd::List(ListCmd::Push(key, item, right))),
+			Err(cause) => self.fail(LPushError::Encode {
+				key: key.into(),
+				cause: cause.into(),
+			}),
+		}
+	}
+
+	pub fn lpush<T>(&mut self, key: &'a str, item: T) -> &mut Self
+	where T: Serialize {
+		self.lrpush(key, item, false, false)
+	}
+
+	pub fn rpush<T>(&mut self, key: &'a str, item: T) -> &mut Self
+	where T: Serialize {
+		self.lrpush(key, item, true, false)
+	}
+
+	pub fn lpush_bytes(&mut self, key: &'a str, item: &[u8]) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Push(key, codec::raw(item), false)))
+	}
+
+	pub fn rpush_bytes(&mut self, key: &'a str, item: &[u8]) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Push(key, codec::raw(item), true)))
+	}
+
+	pub fn lpush_exists<T>(&mut self, key: &'a str, item: T) -> &mut Self
+	where T: Serialize {
+		self.lrpush(key, item, false, true)
+	}
+
+	pub fn rpush_exists<T>(&mut self, key: &'a str, item: T) -> &mut Self
+	where T: Serialize {
+		self.lrpush(key, item, true, true)
+	}
+
+	pub fn lpush_exists_bytes(&mut self, key: &'a str, item: &[u8]) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::PushX(key, codec::raw(item), false)))
+	}
+
+	pub fn rpush_exists_bytes(&mut self, key: &'a str, item: &[u8]) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::PushX(key, codec::raw(item), true)))
+	}
+
+	pub fn lrange(&mut self, key: &'a str, start: isize, stop: isize) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Range(key, start, stop)))
+	}
+
+	pub fn lrem(&mut self, key: &'a str, index: isize) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Rem(key, index)))
+	}
+
+	pub fn lscan(&mut self, key: &'a str, scan: &Scan<'a>) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Scan(key, scan.cursor, scan.count)))
+	}
+
+	pub fn lset<T>(&mut self, key: &'a str, index: isize, item: T) -> &mut Self
+	where T: Serialize {
+		match self.codec.encode(&item) {
+			Ok(item) => self.queue(Cmd::List(ListCmd::Set(key, index, item))),
+			Err(cause) => self.fail(LSetError::Encode {
+				key: key.into(),
+				index,
+				cause: cause.into(),
+			}),
+		}
+	}
+
+	pub fn lset_bytes(&mut self, key: &'a str, index: isize, item: &[u8]) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Set(key, index, codec::raw(item))))
+	}
+
+	pub fn ltrim(&mut self, key: &'a str, start: isize, stop: isize) -> &mut Self {
+		self.queue(Cmd::List(ListCmd::Trim(key, start, stop)))
+	}
+}
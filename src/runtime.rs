@@ -0,0 +1,75 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+
+use std::{io, time::Duration};
+
+use futures::future::{BoxFuture, Future, FutureExt};
+
+#[cfg(not(any(feature = "async-std", feature = "tokio")))]
+compile_error!("enable either the `async-std` or the `tokio` feature of kadis");
+
+/// Executor that a node runs its background tasks on.
+pub trait Runtime {
+	/// Runs `future` in the background, failing if there is no executor to run it on.
+	fn spawn<F>(future: F) -> io::Result<()>
+	where F: Future<Output = ()> + Send + 'static;
+
+	fn sleep(duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+#[cfg(feature = "async-std")]
+pub struct AsyncStd;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStd {
+	fn spawn<F>(future: F) -> io::Result<()>
+	where F: Future<Output = ()> + Send + 'static {
+		async_std::task::spawn(future);
+		Ok(())
+	}
+
+	fn sleep(duration: Duration) -> BoxFuture<'static, ()> {
+		async_std::task::sleep(duration).boxed()
+	}
+}
+
+/// Runs on the tokio runtime the node is created in, which must be tokio 0.3
+/// to match the TCP transport of libp2p.
+#[cfg(feature = "tokio")]
+pub struct Tokio;
+
+#[cfg(feature = "tokio")]
+impl Runtime for Tokio {
+	fn spawn<F>(future: F) -> io::Result<()>
+	where F: Future<Output = ()> + Send + 'static {
+		let handle = tokio::runtime::Handle::try_current()
+			.map_err(|err| io::Error::new(io::ErrorKind::NotFound, err))?;
+		handle.spawn(future);
+		Ok(())
+	}
+
+	fn sleep(duration: Duration) -> BoxFuture<'static, ()> {
+		tokio::time::sleep(duration).boxed()
+	}
+}
+
+/// Runtime picked by the enabled features, preferring tokio when both are on.
+#[cfg(feature = "tokio")]
+pub type DefaultRuntime = Tokio;
+
+#[cfg(all(feature = "async-std", not(feature = "tokio")))]
+pub type DefaultRuntime = AsyncStd;
@@ -0,0 +1,143 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+
+/// Where a scan starts, how many entries each page reads and which hash
+/// fields or keys it returns.
+#[derive(Clone, Copy, Debug)]
+pub struct Scan<'a> {
+	pub(crate) cursor: usize,
+	pub(crate) count: usize,
+	pub(crate) pattern: Option<&'a str>,
+}
+
+impl Default for Scan<'_> {
+	fn default() -> Self {
+		Self {
+			cursor: 0,
+			count: 10,
+			pattern: None,
+		}
+	}
+}
+
+impl<'a> Scan<'a> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Cursor returned by a previous page, or 0 to start from the beginning.
+	pub fn cursor(&self, cursor: usize) -> Self {
+		Self {
+			cursor,
+			..*self
+		}
+	}
+
+	/// How many entries each page looks at. Pages of a filtered hash scan can
+	/// hold fewer, like Redis' `COUNT`.
+	pub fn count(&self, count: usize) -> Self {
+		Self {
+			count: count.max(1),
+			..*self
+		}
+	}
+
+	/// Only returns hash fields or keys matching the glob `pattern`, which may
+	/// use `*`, `?`, `[...]` and `\` escapes. List scans ignore it.
+	pub fn pattern(&self, pattern: &'a str) -> Self {
+		Self {
+			pattern: Some(pattern),
+			..*self
+		}
+	}
+}
+
+/// Matches `text` against a Redis-style glob pattern.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+	let pattern: Vec<char> = pattern.chars().collect();
+	let text: Vec<char> = text.chars().collect();
+	glob(&pattern, &text)
+}
+
+fn glob(pattern: &[char], text: &[char]) -> bool {
+	match pattern.first() {
+		None => text.is_empty(),
+		Some('*') => {
+			let rest = &pattern[pattern.iter().take_while(|c| **c == '*').count()..];
+			rest.is_empty() || (0..=text.len()).any(|skip| glob(rest, &text[skip..]))
+		},
+		Some('?') => !text.is_empty() && glob(&pattern[1..], &text[1..]),
+		Some('[') => match (text.first(), class(&pattern[1..], text.first())) {
+			(Some(_), Some((true, rest))) => glob(rest, &text[1..]),
+			_ => false,
+		},
+		Some('\\') if pattern.len() > 1 => text.first() == Some(&pattern[1]) && glob(&pattern[2..], &text[1..]),
+		Some(c) => text.first() == Some(c) && glob(&pattern[1..], &text[1..]),
+	}
+}
+
+// Whether `c` is in the class starting at `pattern`, and the pattern after it
+fn class<'p>(pattern: &'p [char], c: Option<&char>) -> Option<(bool, &'p [char])> {
+	let c = *c?;
+	let (negate, mut i) = match pattern.first() {
+		Some('^') => (true, 1),
+		_ => (false, 0),
+	};
+	let mut matched = false;
+
+	while i < pattern.len() && pattern[i] != ']' {
+		if pattern[i] == '\\' && i + 1 < pattern.len() {
+			i += 1;
+		}
+		let low = pattern[i];
+		if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+			let high = pattern[i + 2];
+			matched |= low.min(high) <= c && c <= low.max(high);
+			i += 3;
+		} else {
+			matched |= low == c;
+			i += 1;
+		}
+	}
+
+	if i < pattern.len() {
+		Some((matched != negate, &pattern[i + 1..]))
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn patterns() {
+		assert!(glob_match("*", ""));
+		assert!(glob_match("user:*", "user:42"));
+		assert!(!glob_match("user:*", "team:42"));
+		assert!(glob_match("h?llo", "hello"));
+		assert!(!glob_match("h?llo", "hllo"));
+		assert!(glob_match("h[ae]llo", "hallo"));
+		assert!(!glob_match("h[^e]llo", "hello"));
+		assert!(glob_match("h[a-c]llo", "hbllo"));
+		assert!(glob_match("a\\*b", "a*b"));
+		assert!(!glob_match("a\\*b", "axb"));
+		assert!(glob_match("*a*b*", "xxaxxbxx"));
+		assert!(!glob_match("h[ae", "ha"));
+	}
+}
@@ -0,0 +1,245 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::convert::TryInto;
+
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::PeerId;
+
+use crate::acl::{AclTable, Permission};
+
+// Signed records share the first byte with the other record headers
+const SIGNED: u8 = 0x50;
+
+/// How strictly records read from or stored on this node are checked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignaturePolicy {
+	/// Signatures are stripped without being checked.
+	Accept,
+	/// Signed records must carry a valid signature, unsigned ones are let through.
+	Verify,
+	/// Every record must carry a valid signature.
+	Require,
+}
+
+/// A record with its envelope removed. `owner` is the user key the record was
+/// written for, which may differ from the record key.
+pub struct Signed<'a> {
+	pub signer: Option<PeerId>,
+	pub owner: &'a str,
+	pub payload: &'a [u8],
+}
+
+// Key, owner and payload are length-prefixed so they cannot be shifted around
+fn message(key: &str, owner: &str, payload: &[u8]) -> Vec<u8> {
+	let mut message = Vec::with_capacity(8 + key.len() + owner.len() + payload.len());
+	message.extend_from_slice(&(key.len() as u32).to_le_bytes());
+	message.extend_from_slice(key.as_bytes());
+	message.extend_from_slice(&(owner.len() as u32).to_le_bytes());
+	message.extend_from_slice(owner.as_bytes());
+	message.extend_from_slice(payload);
+	message
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+	if data.len() < len {
+		return Err("signed record is truncated".into());
+	}
+	let (head, tail) = data.split_at(len);
+	*data = tail;
+	Ok(head)
+}
+
+pub fn is_signed(record: &[u8]) -> bool {
+	record.first() == Some(&SIGNED)
+}
+
+/// Wraps `payload` in an envelope signed by `keypair`:
+/// [SIGNED][key len u8][public key][owner len u16][owner][sig len u8][signature][payload]
+pub fn sign(keypair: &Keypair, key: &str, owner: &str, payload: &[u8]) -> Result<Vec<u8>, String> {
+	let public = keypair.public().into_protobuf_encoding();
+	let signature = keypair.sign(&message(key, owner, payload)).map_err(|err| err.to_string())?;
+	if public.len() > u8::MAX as usize || signature.len() > u8::MAX as usize || owner.len() > u16::MAX as usize {
+		return Err("signature envelope field is too long".into());
+	}
+
+	let mut record = Vec::with_capacity(5 + public.len() + owner.len() + signature.len() + payload.len());
+	record.push(SIGNED);
+	record.push(public.len() as u8);
+	record.extend(public);
+	record.extend_from_slice(&(owner.len() as u16).to_le_bytes());
+	record.extend_from_slice(owner.as_bytes());
+	record.push(signature.len() as u8);
+	record.extend(signature);
+	record.extend_from_slice(payload);
+	Ok(record)
+}
+
+/// Removes the envelope from a record stored under `key`, checking the
+/// signature unless `verify` is false. Unsigned records have no signer.
+pub fn open<'a>(key: &str, record: &'a [u8], verify: bool) -> Result<Signed<'a>, String> {
+	if !is_signed(record) {
+		return Ok(Signed {
+			signer: None,
+			owner: "",
+			payload: record,
+		});
+	}
+
+	let mut data = &record[1..];
+	let public_len = take(&mut data, 1)?[0] as usize;
+	let public = take(&mut data, public_len)?;
+	let owner_len = u16::from_le_bytes(take(&mut data, 2)?.try_into().unwrap_or_default()) as usize;
+	let owner = std::str::from_utf8(take(&mut data, owner_len)?).map_err(|err| err.to_string())?;
+	let signature_len = take(&mut data, 1)?[0] as usize;
+	let signature = take(&mut data, signature_len)?;
+	let payload = data;
+
+	let public = PublicKey::from_protobuf_encoding(public).map_err(|err| err.to_string())?;
+	if verify && !public.verify(&message(key, owner, payload), signature) {
+		return Err("signature does not match".into());
+	}
+
+	Ok(Signed {
+		signer: Some(PeerId::from(public)),
+		owner,
+		payload,
+	})
+}
+
+/// Restricts which peers may write keys starting with a given prefix.
+#[derive(Clone, Default)]
+pub struct Writers {
+	prefixes: Vec<(String, Vec<PeerId>)>,
+}
+
+impl Writers {
+	pub fn insert(&mut self, prefix: &str, peers: &[PeerId]) {
+		match self.prefixes.iter_mut().find(|(p, _)| p == prefix) {
+			Some((_, writers)) => writers.extend_from_slice(peers),
+			None => self.prefixes.push((prefix.into(), peers.to_vec())),
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.prefixes.is_empty()
+	}
+
+	/// Whether `key` has writers configured, using the longest matching prefix.
+	pub fn restricted(&self, key: &str) -> Option<&[PeerId]> {
+		self.prefixes.iter()
+			.filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+			.max_by_key(|(prefix, _)| prefix.len())
+			.map(|(_, writers)| writers.as_slice())
+	}
+
+	pub fn allowed(&self, key: &str, signer: Option<&PeerId>) -> bool {
+		match self.restricted(key) {
+			Some(writers) => match signer {
+				Some(signer) => writers.contains(signer),
+				None => false,
+			},
+			None => true,
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct Validator {
+	pub policy: SignaturePolicy,
+	pub writers: Writers,
+}
+
+impl Validator {
+	/// Checks a record read back while working on the user key `scope` and
+	/// returns its payload. Chunks pass no scope since their digest covers them.
+	pub fn check<'a>(&self, key: &str, record: &'a [u8], scope: Option<&str>, acls: &AclTable) -> Result<&'a [u8], String> {
+		let restricted = match scope {
+			Some(scope) => self.writers.restricted(scope).is_some() || !acls.permits(scope, None, Permission::Write),
+			None => false,
+		};
+		let verify = self.policy != SignaturePolicy::Accept || restricted;
+		let signed = open(key, record, verify)?;
+		self.check_signer(signed.signer.as_ref(), scope, acls)?;
+		Ok(signed.payload)
+	}
+
+	/// Checks that `signer`, or nobody for records written before signing, may
+	/// have written a record read back while working on `scope`.
+	pub fn check_signer(&self, signer: Option<&PeerId>, scope: Option<&str>, acls: &AclTable) -> Result<(), String> {
+		if signer.is_none() && self.policy == SignaturePolicy::Require {
+			return Err("record is not signed".into());
+		}
+		if let Some(scope) = scope {
+			if !self.writers.allowed(scope, signer) {
+				return Err(format!("record was not written by an allowed writer of {}", scope));
+			}
+			if !acls.permits(scope, signer, Permission::Write) {
+				return Err(format!("record was not written by a writer the ACL of {} allows", scope));
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use libp2p::identity::Keypair;
+	use libp2p::PeerId;
+
+	use super::{open, sign, SignaturePolicy, Validator, Writers};
+	use crate::acl::{Acl, AclTable};
+
+	#[test]
+	fn sign_and_check() {
+		let alice = Keypair::generate_ed25519();
+		let bob = Keypair::generate_ed25519();
+
+		let record = sign(&alice, "kh-team-a:cats-name", "team-a:cats", b"\0Herbert").unwrap();
+		let signed = open("kh-team-a:cats-name", &record, true).unwrap();
+		assert_eq!(signed.signer, Some(PeerId::from(alice.public())));
+		assert_eq!(signed.owner, "team-a:cats");
+		assert_eq!(signed.payload, b"\0Herbert");
+		assert!(open("kh-team-a:cats-color", &record, true).is_err());
+
+		let mut tampered = record.clone();
+		*tampered.last_mut().unwrap() ^= 1;
+		assert!(open("kh-team-a:cats-name", &tampered, true).is_err());
+
+		let mut writers = Writers::default();
+		writers.insert("team-a:", &[PeerId::from(alice.public())]);
+		let validator = Validator {
+			policy: SignaturePolicy::Verify,
+			writers,
+		};
+		let mut acls = AclTable::new(&[PeerId::from(alice.public())]);
+		assert!(validator.check("kh-team-a:cats-name", &record, Some("team-a:cats"), &acls).is_ok());
+		assert!(validator.check("kh-team-a:cats-name", b"\0Herbert", Some("team-a:cats"), &acls).is_err());
+		assert_eq!(validator.check("kh-cats-name", b"\0Herbert", Some("cats"), &acls).unwrap(), b"\0Herbert");
+
+		let forged = sign(&bob, "kh-team-a:cats-name", "team-a:cats", b"\0Garfield").unwrap();
+		assert!(validator.check("kh-team-a:cats-name", &forged, Some("team-a:cats"), &acls).is_err());
+
+		// ACL writers are enforced on reads as well
+		let admins = [PeerId::from(alice.public())];
+		acls.insert("team-b:", Acl::new(&admins).write(&admins));
+		let record = sign(&bob, "kh-team-b:cats-name", "team-b:cats", b"\0Herbert").unwrap();
+		assert!(validator.check("kh-team-b:cats-name", &record, Some("team-b:cats"), &acls).is_err());
+		assert!(validator.check("kh-team-b:cats-name", b"\0Herbert", Some("team-b:cats"), &acls).is_err());
+		let record = sign(&alice, "kh-team-b:cats-name", "team-b:cats", b"\0Herbert").unwrap();
+		assert!(validator.check("kh-team-b:cats-name", &record, Some("team-b:cats"), &acls).is_ok());
+	}
+}
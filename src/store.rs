@@ -0,0 +1,217 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::{
+	borrow::Cow,
+	sync::{Arc, Mutex},
+};
+
+use libp2p::kad::record::store::{Error, MemoryStore, MemoryStoreConfig, RecordStore, Result};
+use libp2p::kad::record::{Key, ProviderRecord, Record};
+use libp2p::PeerId;
+
+use crate::acl::{self, Acl, AclTable, Permission};
+use crate::chunk::{self, CHUNK_SIZE};
+use crate::compression::MAX_VALUE_SIZE;
+use crate::keys;
+use crate::signature::{self, Signed, SignaturePolicy, Validator};
+
+// Enough for several of the largest values alongside everything else
+const MAX_RECORDS: usize = 16 * MAX_VALUE_SIZE / CHUNK_SIZE;
+// Chunks and manifests plus their signatures, which Kademlia refuses above 16 KiB anyway
+const MAX_RECORD_SIZE: usize = 16 * 1024;
+
+/// Record store that refuses records failing the node's signature policy or
+/// ACLs, including overwrites of keys by peers that are not allowed to write them.
+pub struct ValidatingStore {
+	inner: MemoryStore,
+	validator: Validator,
+	acls: Arc<Mutex<AclTable>>,
+}
+
+impl ValidatingStore {
+	pub fn new(local_id: PeerId, validator: Validator, acls: Arc<Mutex<AclTable>>) -> Self {
+		Self {
+			inner: MemoryStore::with_config(local_id, MemoryStoreConfig {
+				max_records: MAX_RECORDS,
+				max_value_bytes: MAX_RECORD_SIZE,
+				..Default::default()
+			}),
+			validator,
+			acls,
+		}
+	}
+
+	/// Checks `record` before it is stored. ACL records are returned so they
+	/// can be applied once stored.
+	fn validate(&self, record: &Record) -> std::result::Result<Option<(String, Acl)>, String> {
+		let key = String::from_utf8_lossy(record.key.as_ref());
+		let acls = self.acls.lock().unwrap();
+		if acl::acl_prefix(&key).is_some() {
+			let (prefix, acl) = acls.validate(&key, &record.value)?;
+			return Ok(Some((prefix.into(), acl)));
+		}
+
+		let verify = self.validator.policy != SignaturePolicy::Accept
+			|| !self.validator.writers.is_empty()
+			|| !acls.is_empty();
+		// Legacy records were never signed, and their values may start with any byte
+		let signed = match keys::is_legacy(&key) {
+			true => Signed {
+				signer: None,
+				owner: "",
+				payload: &record.value,
+			},
+			false => signature::open(&key, &record.value, verify)?,
+		};
+
+		// Chunks are content-addressed, so anyone may store them as long as they match
+		if chunk::is_chunk_key(&key) {
+			return match chunk::matches_key(&key, signed.payload) {
+				true => Ok(None),
+				false => Err("chunk does not match its key".into()),
+			};
+		}
+
+		let signer = signed.signer.as_ref();
+		if signer.is_none() && self.validator.policy == SignaturePolicy::Require {
+			return Err("record is not signed".into());
+		}
+		if signer.is_some() && record.publisher.as_ref() != signer {
+			return Err("publisher does not match the signer".into());
+		}
+		let owner = keys::owner(&key, signed.owner);
+		if !self.validator.writers.allowed(owner, signer) {
+			return Err(format!("signer may not write {}", owner));
+		}
+		if !acls.permits(owner, signer, Permission::Write) {
+			return Err(format!("ACL does not let signer write {}", owner));
+		}
+
+		if let Some(existing) = self.inner.get(&record.key) {
+			let existing_owner = match keys::is_legacy(&key) {
+				true => "",
+				false => signature::open(&key, &existing.value, false)?.owner,
+			};
+			let owner = keys::owner(&key, existing_owner);
+			if !self.validator.writers.allowed(owner, signer)
+				|| !acls.permits(owner, signer, Permission::Write) {
+				return Err(format!("signer may not overwrite {}", owner));
+			}
+		}
+
+		Ok(None)
+	}
+}
+
+impl<'a> RecordStore<'a> for ValidatingStore {
+	type RecordsIter = <MemoryStore as RecordStore<'a>>::RecordsIter;
+	type ProvidedIter = <MemoryStore as RecordStore<'a>>::ProvidedIter;
+
+	fn get(&'a self, k: &Key) -> Option<Cow<'a, Record>> {
+		self.inner.get(k)
+	}
+
+	fn put(&'a mut self, r: Record) -> Result<()> {
+		let acl = match self.validate(&r) {
+			Ok(acl) => acl,
+			Err(reason) => {
+				log::warn!("Refusing record {}: {}", String::from_utf8_lossy(r.key.as_ref()), reason);
+				// The store has no error for invalid records
+				return Err(Error::ValueTooLarge);
+			},
+		};
+		self.inner.put(r)?;
+		if let Some((prefix, acl)) = acl {
+			self.acls.lock().unwrap().insert(&prefix, acl);
+		}
+		Ok(())
+	}
+
+	fn remove(&'a mut self, k: &Key) {
+		self.inner.remove(k)
+	}
+
+	fn records(&'a self) -> Self::RecordsIter {
+		self.inner.records()
+	}
+
+	fn add_provider(&'a mut self, record: ProviderRecord) -> Result<()> {
+		self.inner.add_provider(record)
+	}
+
+	fn providers(&'a self, key: &Key) -> Vec<ProviderRecord> {
+		self.inner.providers(key)
+	}
+
+	fn provided(&'a self) -> Self::ProvidedIter {
+		self.inner.provided()
+	}
+
+	fn remove_provider(&'a mut self, k: &Key, p: &PeerId) {
+		self.inner.remove_provider(k, p)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::{Arc, Mutex};
+
+	use libp2p::identity::Keypair;
+	use libp2p::kad::record::store::RecordStore;
+	use libp2p::kad::record::{Key, Record};
+	use libp2p::PeerId;
+
+	use super::ValidatingStore;
+	use crate::acl::AclTable;
+	use crate::keys;
+	use crate::signature::{self, SignaturePolicy, Validator, Writers};
+
+	fn record(keypair: &Keypair, key: &str, owner: &str) -> Record {
+		Record {
+			key: Key::new(&key),
+			value: signature::sign(keypair, key, owner, b"\0Herbert").unwrap(),
+			publisher: Some(PeerId::from(keypair.public())),
+			expires: None,
+		}
+	}
+
+	#[test]
+	fn owner_from_key() {
+		let alice = Keypair::generate_ed25519();
+		let bob = Keypair::generate_ed25519();
+		let local = PeerId::random();
+
+		let mut writers = Writers::default();
+		writers.insert("team-a:", &[PeerId::from(alice.public())]);
+		let validator = Validator {
+			policy: SignaturePolicy::Verify,
+			writers,
+		};
+		let acls = Arc::new(Mutex::new(AclTable::new(&[])));
+		let mut store = ValidatingStore::new(local, validator, acls);
+
+		// Claiming to write for another user key does not get around the writers
+		let key = keys::hash_field("team-a:cats", "name");
+		assert!(store.put(record(&bob, key.as_str(), "bob:cats")).is_err());
+		assert!(store.put(record(&bob, key.as_str(), "team-a:cats")).is_err());
+		assert!(store.put(record(&alice, key.as_str(), "team-a:cats")).is_ok());
+
+		// Only writers of a namespace may flush it
+		assert!(store.put(record(&bob, "kg-team-a:dev", "bob:")).is_err());
+		assert!(store.put(record(&alice, "kg-team-a:dev", "team-a:dev")).is_ok());
+	}
+}
@@ -0,0 +1,302 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+	pin::Pin,
+	sync::{Arc, Mutex},
+	task::{Context, Poll, Waker},
+	time::Duration,
+};
+
+use futures::future::{BoxFuture, FutureExt};
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::core::{
+	transport::{memory::Channel, Boxed, MemoryTransport, Transport},
+	ConnectedPoint,
+};
+use libp2p::multiaddr::{Multiaddr, Protocol};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::runtime::{DefaultRuntime, Runtime};
+
+struct FaultState {
+	seed: u64,
+	// Pairs of memory ports that cannot reach each other, lowest first
+	partitions: HashSet<(u64, u64)>,
+	killed: HashSet<u64>,
+	drop_rate: f64,
+	delay: Duration,
+	// Streams waiting to read, woken whenever faults change so that cut
+	// connections close right away instead of once they are next used
+	readers: HashMap<u64, Waker>,
+	next_stream: u64,
+}
+
+impl FaultState {
+	fn changed(&mut self) {
+		for (_, reader) in self.readers.drain() {
+			reader.wake();
+		}
+	}
+}
+
+/// Faults applied to connections between nodes on the memory transport.
+///
+/// Every connection draws random drops from its own RNG, seeded from the seed
+/// given at creation and the ports at both ends, so a test replays the same
+/// faults as long as each connection sends the same messages.
+#[derive(Clone)]
+pub struct Faults {
+	state: Arc<Mutex<FaultState>>,
+}
+
+fn pair(a: u64, b: u64) -> (u64, u64) {
+	(a.min(b), a.max(b))
+}
+
+fn memory_port(address: &Multiaddr) -> Option<u64> {
+	address.iter().find_map(|protocol| match protocol {
+		Protocol::Memory(port) => Some(port),
+		_ => None,
+	})
+}
+
+fn reset() -> io::Error {
+	io::Error::new(io::ErrorKind::ConnectionReset, "connection cut by fault injection")
+}
+
+impl Faults {
+	pub fn new(seed: u64) -> Self {
+		Self {
+			state: Arc::new(Mutex::new(FaultState {
+				seed,
+				partitions: HashSet::new(),
+				killed: HashSet::new(),
+				drop_rate: 0.0,
+				delay: Duration::from_secs(0),
+				readers: HashMap::new(),
+				next_stream: 0,
+			})),
+		}
+	}
+
+	/// Cuts every connection between a port in `a` and a port in `b`.
+	pub fn partition(&self, a: &[u64], b: &[u64]) {
+		let mut state = self.state.lock().unwrap();
+		for x in a {
+			for y in b {
+				state.partitions.insert(pair(*x, *y));
+			}
+		}
+		state.changed();
+	}
+
+	/// Removes all partitions.
+	pub fn heal(&self) {
+		let mut state = self.state.lock().unwrap();
+		state.partitions.clear();
+		state.changed();
+	}
+
+	/// Cuts the node on `port` off from every other node.
+	pub fn kill(&self, port: u64) {
+		let mut state = self.state.lock().unwrap();
+		state.killed.insert(port);
+		state.changed();
+	}
+
+	pub fn revive(&self, port: u64) {
+		self.state.lock().unwrap().killed.remove(&port);
+	}
+
+	/// Chance of dropping each message sent. Connections are encrypted, so a
+	/// dropped message resets the connection it was sent on.
+	pub fn drop_rate(&self, drop_rate: f64) {
+		self.state.lock().unwrap().drop_rate = drop_rate.clamp(0.0, 1.0);
+	}
+
+	/// Delays every message sent by `delay`.
+	pub fn delay(&self, delay: Duration) {
+		self.state.lock().unwrap().delay = delay;
+	}
+
+	fn blocked(&self, local: u64, remote: Option<u64>) -> bool {
+		let state = self.state.lock().unwrap();
+		if state.killed.contains(&local) {
+			return true;
+		}
+		match remote {
+			Some(remote) => state.killed.contains(&remote) || state.partitions.contains(&pair(local, remote)),
+			None => false,
+		}
+	}
+
+	// RNG for the messages sent from `local` to `remote`
+	fn rng(&self, local: u64, remote: Option<u64>) -> StdRng {
+		let seed = self.state.lock().unwrap().seed;
+		StdRng::seed_from_u64(seed ^ local.rotate_left(32) ^ remote.unwrap_or(u64::MAX))
+	}
+
+	/// Decides the fate of a message about to be sent with `rng`, returning
+	/// how long to hold it back for.
+	fn send(&self, local: u64, remote: Option<u64>, rng: &mut StdRng) -> io::Result<Duration> {
+		if self.blocked(local, remote) {
+			return Err(reset());
+		}
+		let state = self.state.lock().unwrap();
+		if state.drop_rate > 0.0 && rng.gen_bool(state.drop_rate) {
+			return Err(reset());
+		}
+		Ok(state.delay)
+	}
+}
+
+/// Memory transport for the node listening on `port`, with `faults` applied
+/// to its connections.
+pub(crate) fn transport(faults: Option<Faults>, port: u64) -> Boxed<FaultStream<Channel<Vec<u8>>>> {
+	MemoryTransport.and_then(move |mut stream, endpoint| {
+		let faults = faults.clone().unwrap_or_else(|| Faults::new(0));
+		async move {
+			// Listeners only see the ephemeral port a connection was dialed
+			// from, so dialers start by sending the port they listen on
+			let remote = match endpoint {
+				ConnectedPoint::Dialer { address } => {
+					stream.write_all(&port.to_le_bytes()).await?;
+					stream.flush().await?;
+					memory_port(&address)
+				},
+				ConnectedPoint::Listener { .. } => {
+					let mut remote = [0; 8];
+					stream.read_exact(&mut remote).await?;
+					Some(u64::from_le_bytes(remote))
+				},
+			};
+			if faults.blocked(port, remote) {
+				return Err(reset());
+			}
+			let id = {
+				let mut state = faults.state.lock().unwrap();
+				state.next_stream += 1;
+				state.next_stream
+			};
+			Ok(FaultStream {
+				id,
+				inner: stream,
+				rng: faults.rng(port, remote),
+				faults,
+				local: port,
+				remote,
+				delay: None,
+				cleared: false,
+			})
+		}
+	}).boxed()
+}
+
+/// Stream that fails once its connection is cut and delays what is written.
+pub struct FaultStream<S> {
+	id: u64,
+	inner: S,
+	rng: StdRng,
+	faults: Faults,
+	local: u64,
+	remote: Option<u64>,
+	delay: Option<BoxFuture<'static, ()>>,
+	// Whether the message being written has already been judged and delayed
+	cleared: bool,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FaultStream<S> {
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+		let this = self.get_mut();
+		if this.faults.blocked(this.local, this.remote) {
+			return Poll::Ready(Err(reset()));
+		}
+		let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+		if res.is_pending() {
+			this.faults.state.lock().unwrap().readers.insert(this.id, cx.waker().clone());
+		}
+		res
+	}
+}
+
+impl<S> Drop for FaultStream<S> {
+	fn drop(&mut self) {
+		self.faults.state.lock().unwrap().readers.remove(&self.id);
+	}
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for FaultStream<S> {
+	fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+		let this = self.get_mut();
+		if !this.cleared {
+			if this.delay.is_none() {
+				let delay = this.faults.send(this.local, this.remote, &mut this.rng)?;
+				if delay > Duration::from_secs(0) {
+					this.delay = Some(DefaultRuntime::sleep(delay));
+				}
+			}
+			if let Some(delay) = &mut this.delay {
+				if delay.poll_unpin(cx).is_pending() {
+					return Poll::Pending;
+				}
+				this.delay = None;
+			}
+			this.cleared = true;
+		}
+
+		let res = Pin::new(&mut this.inner).poll_write(cx, buf);
+		if res.is_ready() {
+			this.cleared = false;
+		}
+		res
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		if this.faults.blocked(this.local, this.remote) {
+			return Poll::Ready(Err(reset()));
+		}
+		Pin::new(&mut this.inner).poll_flush(cx)
+	}
+
+	fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Pin::new(&mut self.get_mut().inner).poll_close(cx)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::Rng;
+
+	use super::Faults;
+
+	#[test]
+	fn replays() {
+		let drops = |faults: &Faults, local, remote| {
+			let mut rng = faults.rng(local, remote);
+			(0..64).map(|_| rng.gen_bool(0.5)).collect::<Vec<_>>()
+		};
+
+		// Each connection replays the same drops however others interleave with it
+		let (a, b) = (Faults::new(7), Faults::new(7));
+		assert_eq!(drops(&a, 1, Some(2)), drops(&b, 1, Some(2)));
+		assert_ne!(drops(&a, 1, Some(2)), drops(&a, 2, Some(1)));
+		assert_ne!(drops(&a, 1, Some(2)), drops(&Faults::new(8), 1, Some(2)));
+	}
+}
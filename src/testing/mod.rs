@@ -0,0 +1,319 @@
+// Copyright (C) 2020 GiraffeKey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Helpers for testing against several nodes in one process.
+
+use std::{
+	sync::atomic::{AtomicU16, Ordering},
+	time::{Duration, Instant},
+};
+
+use libp2p::Multiaddr;
+
+use crate::runtime::{DefaultRuntime, Runtime};
+use crate::{Kadis, KadisBuilder, Keypair, NodeInitError, PeerId};
+
+pub(crate) mod faults;
+
+pub use faults::Faults;
+
+// Memory ports are shared by the whole process, so clusters never reuse one
+static NEXT_PORT: AtomicU16 = AtomicU16::new(1);
+
+/// Nodes connected to each other over libp2p's memory transport.
+pub struct Cluster {
+	nodes: Vec<Kadis>,
+	ports: Vec<u16>,
+	addresses: Vec<Multiaddr>,
+	faults: Faults,
+}
+
+impl Cluster {
+	pub fn new(size: usize) -> Result<Self, NodeInitError> {
+		Self::with_builder(size, &KadisBuilder::default())
+	}
+
+	pub fn with_builder(size: usize, builder: &KadisBuilder<'_>) -> Result<Self, NodeInitError> {
+		Self::with_seed(size, builder, 0)
+	}
+
+	/// Creates `size` nodes from `builder`, replacing its port, bootstraps and
+	/// transport. Every node is told about every other one, and `seed` seeds
+	/// the faults injected into their connections.
+	pub fn with_seed(size: usize, builder: &KadisBuilder<'_>, seed: u64) -> Result<Self, NodeInitError> {
+		let identities: Vec<_> = (0..size).map(|_| Keypair::generate_ed25519()).collect();
+		Self::with_identities(&identities, builder, seed)
+	}
+
+	/// Like [`Cluster::with_seed`], with a node for each of `identities`.
+	pub fn with_identities(identities: &[Keypair], builder: &KadisBuilder<'_>, seed: u64) -> Result<Self, NodeInitError> {
+		let size = identities.len();
+		let faults = Faults::new(seed);
+		let mut nodes = Vec::with_capacity(size);
+		let mut ports = Vec::with_capacity(size);
+		let mut addresses = Vec::with_capacity(size);
+		for identity in identities {
+			let port = NEXT_PORT.fetch_add(1, Ordering::Relaxed);
+			let address = format!("/memory/{}", port);
+			let builder = builder.identity(identity.clone()).memory(true).faults(faults.clone());
+			nodes.push(builder.bootstraps(&[]).port(port).init()?);
+			ports.push(port);
+			addresses.push(address.parse().map_err(|_| NodeInitError::ParseAddress { address })?);
+		}
+
+		let cluster = Self { nodes, ports, addresses, faults };
+		for index in 0..size {
+			cluster.connect(index);
+		}
+		Ok(cluster)
+	}
+
+	// Failed dials drop addresses from routing tables, so they are added again
+	// whenever a node may have become reachable
+	fn connect(&self, index: usize) {
+		for other in 0..self.len() {
+			if other != index {
+				self.nodes[index].node.add_peer(&self.peer_id(other), self.addresses[other].clone());
+				self.nodes[other].node.add_peer(&self.peer_id(index), self.addresses[index].clone());
+			}
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.nodes.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.nodes.is_empty()
+	}
+
+	pub fn node(&mut self, index: usize) -> &mut Kadis {
+		&mut self.nodes[index]
+	}
+
+	pub fn nodes(&mut self) -> &mut [Kadis] {
+		&mut self.nodes
+	}
+
+	pub fn peer_id(&self, index: usize) -> PeerId {
+		self.nodes[index].peer_id()
+	}
+
+	pub fn address(&self, index: usize) -> &Multiaddr {
+		&self.addresses[index]
+	}
+
+	/// Faults injected into the cluster, for dropping and delaying messages.
+	pub fn faults(&self) -> &Faults {
+		&self.faults
+	}
+
+	fn ports(&self, indexes: &[usize]) -> Vec<u64> {
+		indexes.iter().map(|index| self.ports[*index].into()).collect()
+	}
+
+	/// Cuts the nodes in `a` off from the nodes in `b`.
+	pub fn partition(&self, a: &[usize], b: &[usize]) {
+		self.faults.partition(&self.ports(a), &self.ports(b));
+	}
+
+	pub fn heal(&self) {
+		self.faults.heal();
+		for index in 0..self.len() {
+			self.connect(index);
+		}
+	}
+
+	/// Cuts a node off from every other node. Its records are kept until it
+	/// is restarted.
+	pub fn kill(&self, index: usize) {
+		self.faults.kill(self.ports[index].into());
+	}
+
+	/// Brings a killed node back with no records, as a fresh process with the
+	/// same identity and address would be.
+	pub fn restart(&self, index: usize) {
+		self.nodes[index].node.clear();
+		self.faults.revive(self.ports[index].into());
+		self.connect(index);
+	}
+
+	/// Whether every node has every other one in its routing table and is
+	/// connected to it.
+	pub fn is_converged(&self) -> bool {
+		self.nodes.iter().all(|node| {
+			node.node.known_peers() + 1 >= self.nodes.len()
+				&& self.nodes.iter()
+					.map(Kadis::peer_id)
+					.filter(|peer_id| *peer_id != node.peer_id())
+					.all(|peer_id| node.node.is_connected(&peer_id))
+		})
+	}
+
+	/// Waits until the cluster has converged, returning false if it has not
+	/// after `timeout`. Idle connections are closed after a while, so nodes
+	/// are reconnected while waiting.
+	pub async fn converge(&self, timeout: Duration) -> bool {
+		let start = Instant::now();
+		let mut attempt = 0;
+		while !self.is_converged() {
+			if start.elapsed() > timeout {
+				return false;
+			}
+			if attempt % 50 == 0 {
+				for index in 0..self.len() {
+					self.connect(index);
+				}
+			}
+			attempt += 1;
+			DefaultRuntime::sleep(Duration::from_millis(10)).await;
+		}
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use async_std::task;
+
+	use super::*;
+	use crate::{Acl, ErrorCode};
+
+	#[test]
+	fn cluster() {
+		let mut cluster = Cluster::new(4).unwrap();
+		task::block_on(async move {
+			assert!(cluster.converge(Duration::from_secs(10)).await);
+
+			let res = cluster.node(0).hset("cats", "herb", &"orange".to_string()).await;
+			assert!(res.is_ok());
+
+			for index in 1..cluster.len() {
+				let res = cluster.node(index).hget::<String>("cats", "herb").await;
+				assert_eq!(res.unwrap(), "orange");
+			}
+		});
+	}
+
+	#[test]
+	fn partition() {
+		let builder = KadisBuilder::default().query_timeout(Duration::from_secs(2));
+		let mut cluster = Cluster::with_seed(3, &builder, 1).unwrap();
+		task::block_on(async move {
+			assert!(cluster.converge(Duration::from_secs(10)).await);
+
+			cluster.partition(&[0], &[1, 2]);
+			let res = cluster.node(0).hset("cats", "herb", &"orange".to_string()).await;
+			assert_eq!(res.unwrap_err().code(), ErrorCode::QuorumFailed);
+
+			let res = cluster.node(1).rpush("dogs", &"rex".to_string()).await;
+			assert!(res.is_ok());
+
+			cluster.heal();
+			assert!(cluster.converge(Duration::from_secs(10)).await);
+			let res = cluster.node(0).hset("cats", "herb", &"orange".to_string()).await;
+			assert!(res.is_ok());
+		});
+	}
+
+	#[test]
+	fn delay() {
+		let builder = KadisBuilder::default().query_timeout(Duration::from_millis(200));
+		let mut cluster = Cluster::with_seed(3, &builder, 2).unwrap();
+		task::block_on(async move {
+			assert!(cluster.converge(Duration::from_secs(10)).await);
+
+			cluster.faults().delay(Duration::from_secs(1));
+			let res = cluster.node(0).rpush("dogs", &"rex".to_string()).await;
+			assert_eq!(res.unwrap_err().code(), ErrorCode::Timeout);
+		});
+	}
+
+	#[test]
+	fn drops() {
+		let builder = KadisBuilder::default().query_timeout(Duration::from_secs(2));
+		let mut cluster = Cluster::with_seed(3, &builder, 4).unwrap();
+		task::block_on(async move {
+			assert!(cluster.converge(Duration::from_secs(10)).await);
+
+			cluster.faults().drop_rate(1.0);
+			let res = cluster.node(0).hset("cats", "herb", &"orange".to_string()).await;
+			let code = res.unwrap_err().code();
+			assert!(code == ErrorCode::QuorumFailed || code == ErrorCode::Timeout, "{:?}", code);
+
+			cluster.faults().drop_rate(0.0);
+			cluster.heal();
+			assert!(cluster.converge(Duration::from_secs(10)).await);
+			let res = cluster.node(0).hset("cats", "herb", &"orange".to_string()).await;
+			assert!(res.is_ok());
+		});
+	}
+
+	#[test]
+	fn acl() {
+		let identities = [Keypair::generate_ed25519(), Keypair::generate_ed25519(), Keypair::generate_ed25519()];
+		let admins = vec![PeerId::from(identities[0].public())];
+		let builder = KadisBuilder::default()
+			.query_timeout(Duration::from_secs(2))
+			.acl_admins(&admins);
+		let mut cluster = Cluster::with_identities(&identities, &builder, 5).unwrap();
+		task::block_on(async move {
+			assert!(cluster.converge(Duration::from_secs(10)).await);
+
+			// Only the root admin may set an ACL with none above it
+			let acl = Acl::new(&admins).write(&admins);
+			let res = cluster.node(1).set_acl("team-a:", &acl).await;
+			assert_eq!(res.unwrap_err().code(), ErrorCode::PermissionDenied);
+			let res = cluster.node(0).set_acl("team-a:", &acl).await;
+			assert!(res.is_ok());
+
+			// Other nodes accept it and enforce it
+			let res = cluster.node(1).acl("team-a:").await;
+			assert_eq!(res.unwrap(), acl);
+			let res = cluster.node(1).hset("team-a:cats", "herb", &"orange".to_string()).await;
+			let code = res.unwrap_err().code();
+			assert!(code == ErrorCode::PermissionDenied || code == ErrorCode::Rejected, "{:?}", code);
+			let takeover = Acl::new(&[cluster.peer_id(1)]);
+			let res = cluster.node(1).set_acl("team-a:", &takeover).await;
+			assert_eq!(res.unwrap_err().code(), ErrorCode::PermissionDenied);
+
+			let res = cluster.node(0).hset("team-a:cats", "herb", &"orange".to_string()).await;
+			assert!(res.is_ok());
+			let res = cluster.node(2).hget::<String>("team-a:cats", "herb").await;
+			assert_eq!(res.unwrap(), "orange");
+		});
+	}
+
+	#[test]
+	fn kill_and_restart() {
+		let builder = KadisBuilder::default().query_timeout(Duration::from_secs(2));
+		let mut cluster = Cluster::with_seed(3, &builder, 3).unwrap();
+		task::block_on(async move {
+			assert!(cluster.converge(Duration::from_secs(10)).await);
+
+			cluster.kill(2);
+			let res = cluster.node(2).hset("cats", "herb", &"orange".to_string()).await;
+			assert_eq!(res.unwrap_err().code(), ErrorCode::QuorumFailed);
+
+			cluster.restart(2);
+			assert!(cluster.converge(Duration::from_secs(10)).await);
+			let res = cluster.node(2).hset("cats", "herb", &"orange".to_string()).await;
+			assert!(res.is_ok());
+			let res = cluster.node(0).hget::<String>("cats", "herb").await;
+			assert_eq!(res.unwrap(), "orange");
+		});
+	}
+}
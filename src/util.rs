@@ -14,27 +14,135 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
-use std::str;
+use std::convert::TryInto;
 
-pub fn split_list(list: Vec<u8>) -> Vec<String> {
-	str::from_utf8(&list).unwrap().split(",").map(|s| s.into()).collect()
+use crate::node::GetError;
+
+// Never valid UTF-8, so indexes can't be mistaken for the legacy comma-joined format
+const INDEX: u8 = 0xff;
+const VERSION: u8 = 1;
+
+/// Serializes hash field names or list item IDs as
+/// [INDEX][VERSION][count u32] followed by [len u32][bytes] for each entry.
+pub fn encode_list(list: &[String]) -> Vec<u8> {
+	let mut data = Vec::with_capacity(6 + list.iter().map(|s| s.len() + 4).sum::<usize>());
+	data.push(INDEX);
+	data.push(VERSION);
+	data.extend_from_slice(&(list.len() as u32).to_le_bytes());
+	for entry in list {
+		data.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+		data.extend_from_slice(entry.as_bytes());
+	}
+	data
+}
+
+fn read_u32(data: &mut &[u8]) -> Option<usize> {
+	if data.len() < 4 {
+		return None;
+	}
+	let (head, tail) = data.split_at(4);
+	*data = tail;
+	Some(u32::from_le_bytes(head.try_into().ok()?) as usize)
+}
+
+/// Reads an index written by `encode_list`, or by older versions that joined
+/// entries with commas.
+pub fn decode_list(data: Vec<u8>) -> Result<Vec<String>, GetError> {
+	if data.first() != Some(&INDEX) {
+		return match String::from_utf8(data) {
+			Ok(list) if list.is_empty() => Ok(Vec::new()),
+			Ok(list) => Ok(list.split(',').map(|s| s.into()).collect()),
+			Err(_) => Err(GetError::Invalid),
+		};
+	}
+	if data.get(1) != Some(&VERSION) {
+		log::warn!("Unknown index version {:?}", data.get(1));
+		return Err(GetError::Invalid);
+	}
+
+	let mut rest = &data[2..];
+	let count = read_u32(&mut rest).ok_or(GetError::Invalid)?;
+	let mut list = Vec::with_capacity(count.min(rest.len() / 4));
+	for _ in 0..count {
+		let len = read_u32(&mut rest).ok_or(GetError::Invalid)?;
+		if rest.len() < len {
+			return Err(GetError::Invalid);
+		}
+		let (entry, tail) = rest.split_at(len);
+		rest = tail;
+		list.push(String::from_utf8(entry.to_vec()).map_err(|_| GetError::Invalid)?);
+	}
+	if !rest.is_empty() {
+		return Err(GetError::Invalid);
+	}
+
+	Ok(list)
+}
+
+/// Unwraps a result from reading a data type's index, returning the
+/// matching `Key*` error for `$key` otherwise.
+#[macro_export]
+macro_rules! key_get {
+    ( $res:expr, $key:expr, $result:ident, $variant:ident, $error:ident ) => {
+        match $res {
+			Ok(value) => value,
+			Err(err) => return match err {
+				GetError::NotFound => $result::$variant(Err($error::KeyNotFound {
+					key: $key.into(),
+				})),
+				GetError::QuorumFailed => $result::$variant(Err($error::KeyQuorumFailed {
+					key: $key.into(),
+				})),
+				GetError::Timeout => $result::$variant(Err($error::KeyTimeout {
+					key: $key.into(),
+				})),
+				GetError::Invalid => $result::$variant(Err($error::KeyInvalid {
+					key: $key.into(),
+				})),
+			},
+		}
+    };
+}
+
+/// Like `key_get!`, for writes to a data type's index.
+#[macro_export]
+macro_rules! key_put {
+    ( $res:expr, $key:expr, $result:ident, $variant:ident, $error:ident ) => {
+        match $res {
+			Ok(value) => value,
+			Err(err) => return match err {
+				PutError::QuorumFailed => $result::$variant(Err($error::KeyQuorumFailed {
+					key: $key.into(),
+				})),
+				PutError::Timeout => $result::$variant(Err($error::KeyTimeout {
+					key: $key.into(),
+				})),
+				PutError::Rejected => $result::$variant(Err($error::KeyRejected {
+					key: $key.into(),
+				})),
+			},
+		}
+    };
 }
 
 #[macro_export]
 macro_rules! get_list {
     ( $node:expr, $key:expr, $result:ident, $variant:ident, $error:ident ) => {
         {
-            match $node.get(&$key).await {
-				Ok(list) => split_list(list),
+            match $node.get(&$key).await.and_then(decode_list) {
+				Ok(list) => list,
 				Err(err) => return match err {
 					GetError::NotFound => $result::$variant(Err($error::KeyNotFound {
-						key: $key.into(),
+						key: $key.name().into(),
 					})),
 					GetError::QuorumFailed => $result::$variant(Err($error::KeyQuorumFailed {
-						key: $key.into(),
+						key: $key.name().into(),
 					})),
 					GetError::Timeout => $result::$variant(Err($error::KeyTimeout {
-						key: $key.into(),
+						key: $key.name().into(),
+					})),
+					GetError::Invalid => $result::$variant(Err($error::KeyInvalid {
+						key: $key.name().into(),
 					})),
 				},
 			}
@@ -46,15 +154,18 @@ macro_rules! get_list {
 macro_rules! get_list_exists {
     ( $node:expr, $key:expr, $result:ident, $variant:ident, $error:ident ) => {
         {
-            match $node.get(&$key).await {
-				Ok(list) => split_list(list),
+            match $node.get(&$key).await.and_then(decode_list) {
+				Ok(list) => list,
 				Err(err) => match err {
 					GetError::NotFound => Vec::<String>::new(),
 					GetError::QuorumFailed => return $result::$variant(Err($error::KeyQuorumFailed {
-						key: $key.into(),
+						key: $key.name().into(),
 					})),
 					GetError::Timeout => return $result::$variant(Err($error::KeyTimeout {
-						key: $key.into(),
+						key: $key.name().into(),
+					})),
+					GetError::Invalid => return $result::$variant(Err($error::KeyInvalid {
+						key: $key.name().into(),
 					})),
 				},
 			}
@@ -66,20 +177,41 @@ macro_rules! get_list_exists {
 macro_rules! join_list {
     ( $node:expr, $key:expr, $list:expr, $result:ident, $variant:ident, $error:ident ) => {
         {
-            let list = $list.join(",");
-			let list = list.as_bytes().to_vec();
+            let list = encode_list(&$list);
 
 			match $node.put(&$key, list).await {
 				Ok(_) => (),
 				Err(err) => return match err {
 					PutError::QuorumFailed => $result::$variant(Err($error::KeyQuorumFailed {
-						key: $key.into(),
+						key: $key.name().into(),
 					})),
 					PutError::Timeout => $result::$variant(Err($error::KeyTimeout {
-						key: $key.into(),
+						key: $key.name().into(),
+					})),
+					PutError::Rejected => $result::$variant(Err($error::KeyRejected {
+						key: $key.name().into(),
 					})),
 				}
 			}
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{decode_list, encode_list};
+
+	#[test]
+	fn index_round_trip() {
+		let list = vec!["a,b".to_string(), String::new(), "ünïcode".into()];
+		assert_eq!(decode_list(encode_list(&list)).unwrap(), list);
+		assert!(decode_list(encode_list(&[])).unwrap().is_empty());
+
+		assert_eq!(decode_list(b"a,b".to_vec()).unwrap(), vec!["a".to_string(), "b".into()]);
+		assert!(decode_list(Vec::new()).unwrap().is_empty());
+
+		let mut truncated = encode_list(&list);
+		truncated.pop();
+		assert!(decode_list(truncated).is_err());
+	}
+}